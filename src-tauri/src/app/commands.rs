@@ -1,16 +1,85 @@
-use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
-use super::AppState;
-use crate::ssh::{Host, Interface, Session, SessionInfo};
+use serde::{ser::SerializeStruct, Deserialize, Serialize};
+use tauri::Manager;
 
-pub struct CmdError(anyhow::Error);
+use super::{inventory::HostDefinition, AppState};
+use crate::{
+    ssh::{
+        sample_throughput, AuthKind, AuthRejected, CommandError, CommandOutput, ConfigHost,
+        FileInfo, ForwardInfo, ForwardSpec, Host, HostKeyError, Interface, InterfaceCounters,
+        ListInterfaces, OutputChunk, ReadTrafficCounters, Session, SessionInfo, Sftp, SshConfig,
+        StreamingCommandExecutor, TerminalEvent, Throughput, TransferProgress, VirtualCommand,
+        DEFAULT_SAMPLE_INTERVAL,
+    },
+    vault::VaultError,
+};
+
+pub enum CmdError {
+    SessionNotFound(usize),
+    HostNotFound(usize),
+    TerminalNotFound(usize),
+    ForwardNotFound(usize),
+    Command(CommandError),
+    AuthFailed,
+    HostKey(HostKeyError),
+    Vault(VaultError),
+    Other(anyhow::Error),
+}
+
+impl CmdError {
+    fn kind(&self) -> &'static str {
+        match self {
+            CmdError::SessionNotFound(_) => "sessionNotFound",
+            CmdError::HostNotFound(_) => "hostNotFound",
+            CmdError::TerminalNotFound(_) => "terminalNotFound",
+            CmdError::ForwardNotFound(_) => "forwardNotFound",
+            CmdError::Command(CommandError::NoSuitableImplementation) => "noSuitableImplementation",
+            CmdError::Command(CommandError::Failed { output, .. }) if output.is_not_found() => "commandNotFound",
+            CmdError::Command(CommandError::Failed { output, .. }) if output.is_permission_denied() => {
+                "permissionDenied"
+            }
+            CmdError::Command(CommandError::Failed { .. }) => "commandFailed",
+            CmdError::Command(CommandError::TimedOut { .. }) => "commandTimedOut",
+            CmdError::Command(CommandError::Cancelled { .. }) => "commandCancelled",
+            CmdError::Command(CommandError::ElevationDenied { .. }) => "elevationDenied",
+            CmdError::AuthFailed => "authFailed",
+            CmdError::HostKey(HostKeyError::Changed { .. }) => "hostKeyChanged",
+            CmdError::HostKey(HostKeyError::Revoked { .. }) => "hostKeyRevoked",
+            CmdError::HostKey(HostKeyError::Rejected { .. }) => "hostKeyRejected",
+            CmdError::Vault(VaultError::Locked) => "vaultLocked",
+            CmdError::Vault(VaultError::WrongPassword) => "wrongMasterPassword",
+            CmdError::Vault(VaultError::EntryNotFound(_)) => "vaultEntryNotFound",
+            CmdError::Other(_) => "other",
+        }
+    }
+}
+
+impl std::fmt::Display for CmdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CmdError::SessionNotFound(id) => write!(f, "No session with id {}", id),
+            CmdError::HostNotFound(id) => write!(f, "No saved host with id {}", id),
+            CmdError::TerminalNotFound(id) => write!(f, "No terminal with id {}", id),
+            CmdError::ForwardNotFound(id) => write!(f, "No port forward with id {}", id),
+            CmdError::Command(e) => e.fmt(f),
+            CmdError::AuthFailed => AuthRejected.fmt(f),
+            CmdError::HostKey(e) => e.fmt(f),
+            CmdError::Vault(e) => e.fmt(f),
+            CmdError::Other(e) => e.fmt(f),
+        }
+    }
+}
 
 impl Serialize for CmdError {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        self.0.to_string().serialize(serializer)
+        let mut state = serializer.serialize_struct("CmdError", 2)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
     }
 }
 
@@ -18,37 +87,598 @@ pub type CmdResult<T> = Result<T, CmdError>;
 
 impl From<anyhow::Error> for CmdError {
     fn from(e: anyhow::Error) -> Self {
-        Self(e)
+        let e = match e.downcast::<CommandError>() {
+            Ok(e) => return Self::Command(e),
+            Err(e) => e,
+        };
+        if e.is::<AuthRejected>() {
+            return Self::AuthFailed;
+        }
+        let e = match e.downcast::<HostKeyError>() {
+            Ok(e) => return Self::HostKey(e),
+            Err(e) => e,
+        };
+        match e.downcast::<VaultError>() {
+            Ok(e) => Self::Vault(e),
+            Err(e) => Self::Other(e),
+        }
     }
 }
 
+/// A password or key passphrase, either given as is or looked up in the vault
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Secret {
+    Plain(String),
+    /// Name of a vault entry
+    Vault(String),
+}
+
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct SessionStartRequest {
     host: Host,
     user: String,
-    password: String,
+    auth: AuthKind,
+    secret: Option<Secret>,
+    /// Id of an existing session to tunnel through
+    jump_session: Option<usize>,
 }
 
 #[tauri::command]
 pub async fn start_session(
     req: SessionStartRequest,
+    app: tauri::AppHandle,
     app_state: tauri::State<'_, AppState>,
 ) -> CmdResult<usize> {
-    let session = Session::connect(req.host.into(), req.user, req.password).await?;
+    let auth = req.auth.with_secret(app_state.resolve_secret(req.secret).await?)?;
+    let context = app_state.connect_context(app.clone());
+    let session = match req.jump_session {
+        Some(jump_id) => {
+            let jump = app_state
+                .get_session(jump_id)
+                .await
+                .ok_or(CmdError::SessionNotFound(jump_id))?;
+            Session::connect_via(jump, req.host.into(), req.user, auth, context).await?
+        }
+        None => Session::connect(req.host.into(), req.user, auth, context).await?,
+    };
     let id = session.id();
-    app_state.add_session(session).await;
+    app_state.add_session(app, session).await;
     Ok(id)
 }
 
+#[tauri::command]
+pub async fn disconnect_session(
+    session_id: usize,
+    app: tauri::AppHandle,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<()> {
+    let changed = app_state.disconnect_session(session_id).await;
+    if let Ok(false) = changed {
+        return Err(CmdError::SessionNotFound(session_id));
+    }
+    // Sent even on failure, the sessions are marked disconnected regardless
+    let _ = app.emit_all("sessions-changed", ());
+    changed?;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn remove_session(
+    session_id: usize,
+    app: tauri::AppHandle,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<()> {
+    let changed = app_state.remove_session(session_id).await;
+    if let Ok(false) = changed {
+        return Err(CmdError::SessionNotFound(session_id));
+    }
+    // Sent even on failure, the sessions are marked disconnected regardless
+    let _ = app.emit_all("sessions-changed", ());
+    changed?;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_hosts(app_state: tauri::State<'_, AppState>) -> CmdResult<Vec<HostDefinition>> {
+    Ok(app_state.get_hosts().await)
+}
+
+#[tauri::command]
+pub async fn save_host(
+    host: HostDefinition,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<usize> {
+    Ok(app_state.save_host(host).await?)
+}
+
+#[tauri::command]
+pub async fn remove_host(host_id: usize, app_state: tauri::State<'_, AppState>) -> CmdResult<()> {
+    if !app_state.remove_host(host_id).await? {
+        return Err(CmdError::HostNotFound(host_id));
+    }
+    Ok(())
+}
+
+/// `secret` overrides the host's vault entry, a plain one is never saved
+#[tauri::command]
+pub async fn connect_host(
+    host_id: usize,
+    secret: Option<Secret>,
+    app: tauri::AppHandle,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<usize> {
+    let host = app_state
+        .get_host(host_id)
+        .await
+        .ok_or(CmdError::HostNotFound(host_id))?;
+    Ok(app_state.connect_host(app, &host, secret).await?)
+}
+
+/// Called by the UI once it listens for prompts, saved hosts are only auto-connected from then on
+#[tauri::command]
+pub async fn frontend_ready(
+    app: tauri::AppHandle,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<()> {
+    if app_state.mark_frontend_ready() {
+        tauri::async_runtime::spawn(async move {
+            app.state::<AppState>().auto_connect(app.clone()).await;
+        });
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn answer_host_key(
+    prompt_id: usize,
+    accept: bool,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<()> {
+    Ok(app_state.host_key_prompts().answer(prompt_id, accept).await?)
+}
+
+#[tauri::command]
+pub async fn answer_keyboard_interactive(
+    prompt_id: usize,
+    answers: Option<Vec<String>>,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<()> {
+    Ok(app_state
+        .keyboard_interactive_prompts()
+        .answer(prompt_id, answers)
+        .await?)
+}
+
+#[derive(Serialize)]
+pub struct VaultStatus {
+    exists: bool,
+    unlocked: bool,
+}
+
+#[tauri::command]
+pub async fn vault_status(app_state: tauri::State<'_, AppState>) -> CmdResult<VaultStatus> {
+    let vault = app_state.vault().lock().await;
+    Ok(VaultStatus {
+        exists: vault.exists(),
+        unlocked: vault.is_unlocked(),
+    })
+}
+
+/// Creates the vault on first use, then connects the saved hosts that were waiting for it
+#[tauri::command]
+pub async fn unlock_vault(
+    master_password: String,
+    app: tauri::AppHandle,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<()> {
+    app_state.vault().lock().await.unlock(&master_password).await?;
+    tauri::async_runtime::spawn(async move {
+        app.state::<AppState>().auto_connect(app.clone()).await;
+    });
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn lock_vault(app_state: tauri::State<'_, AppState>) -> CmdResult<()> {
+    app_state.vault().lock().await.lock();
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_vault_entries(app_state: tauri::State<'_, AppState>) -> CmdResult<Vec<String>> {
+    Ok(app_state.vault().lock().await.entries()?)
+}
+
+#[tauri::command]
+pub async fn set_vault_entry(
+    entry: String,
+    secret: String,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<()> {
+    Ok(app_state.vault().lock().await.set(entry, secret)?)
+}
+
+#[tauri::command]
+pub async fn remove_vault_entry(entry: String, app_state: tauri::State<'_, AppState>) -> CmdResult<()> {
+    Ok(app_state.vault().lock().await.remove(&entry)?)
+}
+
 #[tauri::command]
 pub async fn get_sessions(app_state: tauri::State<'_, AppState>) -> CmdResult<Vec<SessionInfo>> {
     Ok(app_state.get_sessions_info().await?)
 }
 
+#[tauri::command]
+pub async fn get_config_hosts() -> CmdResult<Vec<ConfigHost>> {
+    Ok(SshConfig::load_user_config()?.hosts())
+}
+
+/// Passing a `command_id` makes the call cancellable through `cancel_command`,
+/// `timeout_ms` replaces the default timeout of each remote command
 #[tauri::command]
 pub async fn get_interfaces(
     session_id: usize,
+    command_id: Option<usize>,
+    timeout_ms: Option<u64>,
     app_state: tauri::State<'_, AppState>,
 ) -> CmdResult<Vec<Interface>> {
-    todo!()
+    let session = app_state
+        .get_session(session_id)
+        .await
+        .ok_or(CmdError::SessionNotFound(session_id))?;
+    let running = command_id
+        .map(|id| app_state.running_commands().start(id))
+        .transpose()?;
+    let commands = session.read().await.commands();
+    let mut executor = commands.executor();
+    if let Some(running) = &running {
+        executor = executor.with_cancel(running.cancel_signal());
+    }
+    if let Some(timeout_ms) = timeout_ms {
+        executor = executor.with_timeout(Duration::from_millis(timeout_ms));
+    }
+    Ok(ListInterfaces.execute(&executor).await?)
+}
+
+/// Byte, packet, error and drop totals of every interface
+#[tauri::command]
+pub async fn get_traffic_counters(
+    session_id: usize,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<Vec<InterfaceCounters>> {
+    let session = app_state
+        .get_session(session_id)
+        .await
+        .ok_or(CmdError::SessionNotFound(session_id))?;
+    let commands = session.read().await.commands();
+    Ok(ReadTrafficCounters.execute(&commands.executor()).await?)
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ThroughputEvent {
+    watch_id: usize,
+    interfaces: Vec<Throughput>,
+}
+
+/// Samples interface rates every `interval_ms`, one second by default, and sends them as
+/// `throughput` events tagged with `watch_id`. Runs until `cancel_command(watch_id)`.
+#[tauri::command]
+pub async fn watch_throughput(
+    session_id: usize,
+    watch_id: usize,
+    interval_ms: Option<u64>,
+    app: tauri::AppHandle,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<()> {
+    let session = app_state
+        .get_session(session_id)
+        .await
+        .ok_or(CmdError::SessionNotFound(session_id))?;
+    let running = app_state.running_commands().start(watch_id)?;
+    let interval = interval_ms.map_or(DEFAULT_SAMPLE_INTERVAL, Duration::from_millis);
+    let on_sample = |interfaces| {
+        let _ = app.emit_all("throughput", ThroughputEvent { watch_id, interfaces });
+    };
+    Ok(sample_throughput(&session, interval, running.cancel_signal(), on_sample).await?)
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct JobOutputEvent {
+    job_id: usize,
+    #[serde(flatten)]
+    chunk: OutputChunk,
+}
+
+/// Runs `command` as is, its output is streamed as `job-output` events tagged with `job_id`.
+/// The job can be stopped with `cancel_command(job_id)`, it has no timeout unless given one.
+/// `elevated` runs it as root through sudo or doas.
+#[tauri::command]
+pub async fn run_command(
+    session_id: usize,
+    job_id: usize,
+    command: String,
+    timeout_ms: Option<u64>,
+    elevated: Option<bool>,
+    app: tauri::AppHandle,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<CommandOutput> {
+    let session = app_state
+        .get_session(session_id)
+        .await
+        .ok_or(CmdError::SessionNotFound(session_id))?;
+    let running = app_state.running_commands().start(job_id)?;
+    // Not holding the session lock, jobs can run for as long as they like
+    let commands = session.read().await.commands();
+    let mut executor = commands.executor().with_cancel(running.cancel_signal());
+    if elevated.unwrap_or_default() {
+        executor = executor.elevated();
+    }
+
+    let mut forward = |chunk| {
+        let _ = app.emit_all("job-output", JobOutputEvent { job_id, chunk });
+    };
+    let timeout = timeout_ms.map(Duration::from_millis);
+    Ok(executor.execute_streaming(&command, timeout, &mut forward).await?)
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct TerminalEventPayload {
+    terminal_id: usize,
+    #[serde(flatten)]
+    event: TerminalEvent,
+}
+
+/// Starts a login shell on a PTY, its output comes back as `terminal-event` events
+#[tauri::command]
+pub async fn open_terminal(
+    session_id: usize,
+    cols: u32,
+    rows: u32,
+    app: tauri::AppHandle,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<usize> {
+    let session = app_state
+        .get_session(session_id)
+        .await
+        .ok_or(CmdError::SessionNotFound(session_id))?;
+    let on_event = move |terminal_id, event| {
+        let _ = app.emit_all("terminal-event", TerminalEventPayload { terminal_id, event });
+    };
+    let session = session.read().await;
+    Ok(session.open_terminal(cols, rows, on_event).await?)
+}
+
+#[tauri::command]
+pub async fn write_terminal(
+    session_id: usize,
+    terminal_id: usize,
+    data: String,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<()> {
+    let session = app_state
+        .get_session(session_id)
+        .await
+        .ok_or(CmdError::SessionNotFound(session_id))?;
+    let terminal = session
+        .read()
+        .await
+        .terminal(terminal_id)
+        .await
+        .ok_or(CmdError::TerminalNotFound(terminal_id))?;
+    Ok(terminal.write(data.into_bytes())?)
+}
+
+#[tauri::command]
+pub async fn resize_terminal(
+    session_id: usize,
+    terminal_id: usize,
+    cols: u32,
+    rows: u32,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<()> {
+    let session = app_state
+        .get_session(session_id)
+        .await
+        .ok_or(CmdError::SessionNotFound(session_id))?;
+    let terminal = session
+        .read()
+        .await
+        .terminal(terminal_id)
+        .await
+        .ok_or(CmdError::TerminalNotFound(terminal_id))?;
+    Ok(terminal.resize(cols, rows)?)
+}
+
+#[tauri::command]
+pub async fn close_terminal(
+    session_id: usize,
+    terminal_id: usize,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<()> {
+    let session = app_state
+        .get_session(session_id)
+        .await
+        .ok_or(CmdError::SessionNotFound(session_id))?;
+    if !session.read().await.close_terminal(terminal_id).await {
+        return Err(CmdError::TerminalNotFound(terminal_id));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_forwards(
+    session_id: usize,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<Vec<ForwardInfo>> {
+    let session = app_state
+        .get_session(session_id)
+        .await
+        .ok_or(CmdError::SessionNotFound(session_id))?;
+    let forwards = session.read().await.forwards().await;
+    Ok(forwards)
+}
+
+/// The returned info has the port actually bound when 0 was asked for
+#[tauri::command]
+pub async fn start_forward(
+    session_id: usize,
+    forward: ForwardSpec,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<ForwardInfo> {
+    let session = app_state
+        .get_session(session_id)
+        .await
+        .ok_or(CmdError::SessionNotFound(session_id))?;
+    Ok(Session::start_forward(&session, forward).await?)
+}
+
+#[tauri::command]
+pub async fn stop_forward(
+    session_id: usize,
+    forward_id: usize,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<()> {
+    let session = app_state
+        .get_session(session_id)
+        .await
+        .ok_or(CmdError::SessionNotFound(session_id))?;
+    if !Session::stop_forward(&session, forward_id).await? {
+        return Err(CmdError::ForwardNotFound(forward_id));
+    }
+    Ok(())
+}
+
+async fn session_sftp(app_state: &AppState, session_id: usize) -> CmdResult<Arc<Sftp>> {
+    let session = app_state
+        .get_session(session_id)
+        .await
+        .ok_or(CmdError::SessionNotFound(session_id))?;
+    let sftp = session.read().await.sftp().await?;
+    Ok(sftp)
+}
+
+#[tauri::command]
+pub async fn sftp_list_dir(
+    session_id: usize,
+    path: String,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<Vec<FileInfo>> {
+    Ok(session_sftp(&app_state, session_id).await?.list_dir(&path).await?)
+}
+
+#[tauri::command]
+pub async fn sftp_stat(
+    session_id: usize,
+    path: String,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<FileInfo> {
+    Ok(session_sftp(&app_state, session_id).await?.stat(&path).await?)
+}
+
+/// For editing text files, use a download for anything else
+#[tauri::command]
+pub async fn sftp_read_file(
+    session_id: usize,
+    path: String,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<String> {
+    let data = session_sftp(&app_state, session_id).await?.read_file(&path).await?;
+    String::from_utf8(data).map_err(|_| anyhow::anyhow!("'{}' is not a text file", path).into())
+}
+
+#[tauri::command]
+pub async fn sftp_write_file(
+    session_id: usize,
+    path: String,
+    contents: String,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<()> {
+    let sftp = session_sftp(&app_state, session_id).await?;
+    Ok(sftp.write_file(&path, contents.as_bytes()).await?)
+}
+
+#[tauri::command]
+pub async fn sftp_mkdir(
+    session_id: usize,
+    path: String,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<()> {
+    Ok(session_sftp(&app_state, session_id).await?.mkdir(&path).await?)
+}
+
+#[tauri::command]
+pub async fn sftp_rename(
+    session_id: usize,
+    from: String,
+    to: String,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<()> {
+    Ok(session_sftp(&app_state, session_id).await?.rename(&from, &to).await?)
+}
+
+#[tauri::command]
+pub async fn sftp_remove(
+    session_id: usize,
+    path: String,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<()> {
+    Ok(session_sftp(&app_state, session_id).await?.remove(&path).await?)
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct TransferProgressEvent {
+    transfer_id: usize,
+    #[serde(flatten)]
+    progress: TransferProgress,
+}
+
+/// Progress is reported as `transfer-progress` events, `cancel_command(transfer_id)` stops it
+#[tauri::command]
+pub async fn sftp_download(
+    session_id: usize,
+    transfer_id: usize,
+    remote_path: String,
+    local_path: PathBuf,
+    app: tauri::AppHandle,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<()> {
+    let sftp = session_sftp(&app_state, session_id).await?;
+    let running = app_state.running_commands().start(transfer_id)?;
+    let cancel = running.cancel_signal();
+    let on_progress = |progress| {
+        let _ = app.emit_all("transfer-progress", TransferProgressEvent { transfer_id, progress });
+        !*cancel.borrow()
+    };
+    Ok(sftp.download(&remote_path, &local_path, on_progress).await?)
+}
+
+/// Progress is reported as `transfer-progress` events, `cancel_command(transfer_id)` stops it
+#[tauri::command]
+pub async fn sftp_upload(
+    session_id: usize,
+    transfer_id: usize,
+    local_path: PathBuf,
+    remote_path: String,
+    app: tauri::AppHandle,
+    app_state: tauri::State<'_, AppState>,
+) -> CmdResult<()> {
+    let sftp = session_sftp(&app_state, session_id).await?;
+    let running = app_state.running_commands().start(transfer_id)?;
+    let cancel = running.cancel_signal();
+    let on_progress = |progress| {
+        let _ = app.emit_all("transfer-progress", TransferProgressEvent { transfer_id, progress });
+        !*cancel.borrow()
+    };
+    Ok(sftp.upload(&local_path, &remote_path, on_progress).await?)
+}
+
+#[tauri::command]
+pub async fn cancel_command(command_id: usize, app_state: tauri::State<'_, AppState>) -> CmdResult<()> {
+    Ok(app_state.running_commands().cancel(command_id)?)
 }
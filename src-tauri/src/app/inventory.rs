@@ -0,0 +1,177 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::ssh::{random_id, AuthKind, Host};
+
+/// A saved host. Only the kind of authentication is kept, never the secrets.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HostDefinition {
+    #[serde(default)]
+    id: usize,
+    host: Host,
+    user: String,
+    auth: AuthKind,
+    /// Where the password or key passphrase is kept, if it is
+    #[serde(default)]
+    vault_entry: Option<String>,
+    /// Saved host to tunnel through
+    #[serde(default)]
+    jump_host: Option<usize>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    notes: String,
+    #[serde(default)]
+    auto_connect: bool,
+}
+
+impl HostDefinition {
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn host(&self) -> &Host {
+        &self.host
+    }
+
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+
+    pub fn auth(&self) -> &AuthKind {
+        &self.auth
+    }
+
+    pub fn vault_entry(&self) -> Option<&str> {
+        self.vault_entry.as_deref()
+    }
+
+    pub fn jump_host(&self) -> Option<usize> {
+        self.jump_host
+    }
+
+    pub fn auto_connect(&self) -> bool {
+        self.auto_connect
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct InventoryFile {
+    hosts: Vec<HostDefinition>,
+}
+
+/// The saved hosts, backed by a JSON file that is rewritten on every change
+pub struct Inventory {
+    path: PathBuf,
+    hosts: Vec<HostDefinition>,
+}
+
+impl Inventory {
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let file: InventoryFile = match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("Invalid host inventory '{}'", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => InventoryFile::default(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read '{}'", path.display())),
+        };
+        Ok(Self { path, hosts: file.hosts })
+    }
+
+    pub fn hosts(&self) -> &[HostDefinition] {
+        &self.hosts
+    }
+
+    pub fn get(&self, id: usize) -> Option<&HostDefinition> {
+        self.hosts.iter().find(|h| h.id == id)
+    }
+
+    /// Adds the host if it has no id yet, replaces the existing one otherwise
+    pub fn save_host(&mut self, mut host: HostDefinition) -> Result<usize> {
+        if host.jump_host == Some(host.id) || host.jump_host.is_some_and(|id| self.get(id).is_none()) {
+            return Err(anyhow::anyhow!("Invalid jump host for {}", host.host.address()));
+        }
+        match self.hosts.iter_mut().find(|h| host.id != 0 && h.id == host.id) {
+            Some(existing) => *existing = host.clone(),
+            None => {
+                host.id = random_id();
+                self.hosts.push(host.clone());
+            }
+        }
+        self.write()?;
+        Ok(host.id)
+    }
+
+    pub fn remove_host(&mut self, id: usize) -> Result<bool> {
+        let len = self.hosts.len();
+        self.hosts.retain(|h| h.id != id);
+        if self.hosts.len() == len {
+            return Ok(false);
+        }
+        for host in self.hosts.iter_mut().filter(|h| h.jump_host == Some(id)) {
+            host.jump_host = None;
+        }
+        self.write()?;
+        Ok(true)
+    }
+
+    fn write(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let contents = serde_json::to_string_pretty(&InventoryFile { hosts: self.hosts.clone() })?;
+        // Write then rename so a crash never leaves a truncated inventory behind
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, contents)
+            .and_then(|_| std::fs::rename(&tmp, &self.path))
+            .with_context(|| format!("Failed to write '{}'", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_inventory_round_trip() {
+        let path = std::env::temp_dir().join(format!("lazylab-inventory-{}.json", std::process::id()));
+        let mut inventory = Inventory::load(path.clone()).unwrap();
+        assert!(inventory.hosts().is_empty());
+
+        let bastion = inventory.save_host(HostDefinition {
+            id: 0,
+            host: Host::new("bastion.lan".to_string(), 22),
+            user: "admin".to_string(),
+            auth: AuthKind::Agent,
+            vault_entry: None,
+            jump_host: None,
+            tags: vec!["edge".to_string()],
+            notes: String::new(),
+            auto_connect: true,
+        }).unwrap();
+        let nas = inventory.save_host(HostDefinition {
+            id: 0,
+            host: Host::new("nas.lan".to_string(), 2222),
+            user: "root".to_string(),
+            auth: AuthKind::Password,
+            vault_entry: Some("nas".to_string()),
+            jump_host: Some(bastion),
+            tags: Vec::new(),
+            notes: "Backups".to_string(),
+            auto_connect: false,
+        }).unwrap();
+        assert_ne!(bastion, nas);
+
+        let reloaded = Inventory::load(path.clone()).unwrap();
+        assert_eq!(reloaded.hosts(), inventory.hosts());
+        assert_eq!(reloaded.get(nas).unwrap().jump_host(), Some(bastion));
+
+        assert!(inventory.remove_host(bastion).unwrap());
+        let reloaded = Inventory::load(path.clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(reloaded.hosts().len(), 1);
+        assert_eq!(reloaded.get(nas).unwrap().jump_host(), None);
+    }
+}
@@ -0,0 +1,71 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::Result;
+use tokio::sync::watch;
+
+/// In-flight remote commands the webview can cancel, keyed by an id it picked
+#[derive(Default)]
+pub struct RunningCommands {
+    cancels: Mutex<HashMap<usize, watch::Sender<bool>>>,
+}
+
+impl RunningCommands {
+    /// The command stays cancellable until the returned guard is dropped
+    pub fn start(&self, id: usize) -> Result<RunningCommand<'_>> {
+        let mut cancels = self.cancels.lock().unwrap();
+        if cancels.contains_key(&id) {
+            return Err(anyhow::anyhow!("A command with id {} is already running", id));
+        }
+        let (sender, receiver) = watch::channel(false);
+        cancels.insert(id, sender);
+        Ok(RunningCommand { commands: self, id, cancel: receiver })
+    }
+
+    pub fn cancel(&self, id: usize) -> Result<()> {
+        self.cancels
+            .lock()
+            .unwrap()
+            .get(&id)
+            .ok_or(anyhow::anyhow!("No running command with id {}", id))?
+            .send_replace(true);
+        Ok(())
+    }
+}
+
+pub struct RunningCommand<'a> {
+    commands: &'a RunningCommands,
+    id: usize,
+    cancel: watch::Receiver<bool>,
+}
+
+impl RunningCommand<'_> {
+    pub fn cancel_signal(&self) -> watch::Receiver<bool> {
+        self.cancel.clone()
+    }
+}
+
+impl Drop for RunningCommand<'_> {
+    fn drop(&mut self) {
+        self.commands.cancels.lock().unwrap().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_running_commands_cancel() {
+        let commands = RunningCommands::default();
+        let running = commands.start(7).unwrap();
+        assert!(commands.start(7).is_err());
+
+        let signal = running.cancel_signal();
+        assert!(!*signal.borrow());
+        commands.cancel(7).unwrap();
+        assert!(*signal.borrow());
+
+        drop(running);
+        assert!(commands.cancel(7).is_err());
+    }
+}
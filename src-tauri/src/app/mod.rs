@@ -1,4 +1,7 @@
 pub mod commands;
+mod inventory;
+mod jobs;
+mod prompter;
 mod state;
 
-pub use state::AppState;
\ No newline at end of file
+pub use state::AppState;
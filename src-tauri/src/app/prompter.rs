@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::Serialize;
+use tauri::Manager;
+use tokio::sync::{oneshot, Mutex};
+
+use super::AppState;
+use crate::ssh::{HostKeyPrompt, KeyboardInteractivePrompt, Prompter};
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct PromptEvent<T> {
+    prompt_id: usize,
+    #[serde(flatten)]
+    prompt: T,
+}
+
+/// Questions sent to the webview that are still waiting for an answer
+pub struct PendingPrompts<T> {
+    prompts: Mutex<HashMap<usize, oneshot::Sender<T>>>,
+}
+
+impl<T> Default for PendingPrompts<T> {
+    fn default() -> Self {
+        Self { prompts: Mutex::default() }
+    }
+}
+
+impl<T> PendingPrompts<T> {
+    pub async fn add(&self) -> (usize, oneshot::Receiver<T>) {
+        let (sender, receiver) = oneshot::channel();
+        let mut prompts = self.prompts.lock().await;
+        let id = loop {
+            let id = rand::random::<u32>() as usize;
+            if !prompts.contains_key(&id) {
+                break id;
+            }
+        };
+        prompts.insert(id, sender);
+        (id, receiver)
+    }
+
+    pub async fn answer(&self, id: usize, answer: T) -> Result<()> {
+        self.prompts
+            .lock()
+            .await
+            .remove(&id)
+            .ok_or(anyhow::anyhow!("No pending prompt with id {}", id))?
+            .send(answer)
+            .map_err(|_| anyhow::anyhow!("Connection for prompt {} is gone", id))
+    }
+}
+
+/// Forwards prompts to the webview as events, answers come back through commands
+pub struct TauriPrompter {
+    app: tauri::AppHandle,
+}
+
+impl TauriPrompter {
+    pub fn new(app: tauri::AppHandle) -> Self {
+        Self { app }
+    }
+
+    async fn ask<T, P>(&self, event: &str, pending: &PendingPrompts<T>, prompt: P) -> Option<T>
+    where
+        P: Serialize + Clone,
+    {
+        let (prompt_id, answer) = pending.add().await;
+        if self.app.emit_all(event, PromptEvent { prompt_id, prompt }).is_err() {
+            return None;
+        }
+        answer.await.ok()
+    }
+}
+
+#[async_trait::async_trait]
+impl Prompter for TauriPrompter {
+    async fn confirm_host_key(&self, prompt: HostKeyPrompt) -> bool {
+        let app_state = self.app.state::<AppState>();
+        self.ask("host-key-prompt", app_state.host_key_prompts(), prompt)
+            .await
+            .unwrap_or(false)
+    }
+
+    async fn keyboard_interactive(&self, prompt: KeyboardInteractivePrompt) -> Option<Vec<String>> {
+        let app_state = self.app.state::<AppState>();
+        self.ask("keyboard-interactive-prompt", app_state.keyboard_interactive_prompts(), prompt)
+            .await
+            .flatten()
+    }
+}
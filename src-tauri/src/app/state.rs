@@ -1,25 +1,82 @@
-use std::sync::Arc;
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 use anyhow::Result;
+use serde::Serialize;
+use tauri::Manager;
 use tokio::sync::{Mutex, RwLock};
 
-use crate::ssh::{Session, SessionInfo};
+use super::{
+    commands::Secret,
+    inventory::{HostDefinition, Inventory},
+    jobs::RunningCommands,
+    prompter::{PendingPrompts, TauriPrompter},
+};
+use crate::{
+    ssh::{ConnectContext, KnownHosts, Session, SessionInfo},
+    vault::Vault,
+};
 
-#[derive(Default)]
 pub struct AppState {
     sessions: Mutex<Vec<Arc<RwLock<Session>>>>,
+    inventory: Mutex<Inventory>,
+    vault: Mutex<Vault>,
+    known_hosts: Arc<KnownHosts>,
+    host_key_prompts: PendingPrompts<bool>,
+    keyboard_interactive_prompts: PendingPrompts<Option<Vec<String>>>,
+    running_commands: RunningCommands,
+    /// Prompts raised before the UI listens would never be answered
+    frontend_ready: AtomicBool,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct AutoConnectFailed {
+    host_id: usize,
+    error: String,
 }
 
 impl AppState {
-    pub fn new() -> Self {
-        Self::default()
+    pub fn new(data_dir: PathBuf) -> Result<Self> {
+        Ok(Self {
+            sessions: Mutex::default(),
+            inventory: Mutex::new(Inventory::load(data_dir.join("hosts.json"))?),
+            vault: Mutex::new(Vault::new(data_dir.join("vault.json"))),
+            known_hosts: Arc::new(KnownHosts::new(data_dir.join("known_hosts"))),
+            host_key_prompts: PendingPrompts::default(),
+            keyboard_interactive_prompts: PendingPrompts::default(),
+            running_commands: RunningCommands::default(),
+            frontend_ready: AtomicBool::new(false),
+        })
     }
 
-    pub async fn add_session(&self, session: Session) {
-        self.sessions
-            .lock()
-            .await
-            .push(Arc::new(RwLock::new(session)));
+    /// True the first time only, a reloaded page doesn't start everything over
+    pub fn mark_frontend_ready(&self) -> bool {
+        !self.frontend_ready.swap(true, Ordering::SeqCst)
+    }
+
+    pub fn connect_context(&self, app: tauri::AppHandle) -> ConnectContext {
+        ConnectContext {
+            known_hosts: self.known_hosts.clone(),
+            prompter: Arc::new(TauriPrompter::new(app)),
+        }
+    }
+
+    /// Tracks the session and keeps it alive, state changes are pushed to the UI
+    pub async fn add_session(&self, app: tauri::AppHandle, session: Session) {
+        let session = Arc::new(RwLock::new(session));
+        let monitored = Arc::downgrade(&session);
+        self.sessions.lock().await.push(session);
+        let _ = app.emit_all("sessions-changed", ());
+
+        tauri::async_runtime::spawn(Session::monitor(monitored, move |info| {
+            let _ = app.emit_all("session-state-changed", info);
+        }));
     }
 
     pub async fn get_sessions_info(&self) -> Result<Vec<SessionInfo>> {
@@ -39,4 +96,172 @@ impl AppState {
         }
         None
     }
+
+    /// The session opened from a saved host, unless it was closed or gave up reconnecting
+    pub async fn find_host_session(&self, host_id: usize) -> Option<Arc<RwLock<Session>>> {
+        let sessions = self.sessions.lock().await;
+        for session in sessions.iter() {
+            let s = session.read().await;
+            if s.host_id() == Some(host_id) && s.state().is_active() {
+                return Some(session.clone());
+            }
+        }
+        None
+    }
+
+    /// Disconnects a session and every session tunneled through it, innermost first.
+    /// They stay listed until removed. A session that fails to say goodbye to its host
+    /// is still marked disconnected, the first such error is returned once all are done.
+    pub async fn disconnect_session(&self, id: usize) -> Result<bool> {
+        // Released before disconnecting, waiting on a session shouldn't block every other command
+        let mut affected = Vec::new();
+        for session in self.sessions.lock().await.iter() {
+            let s = session.read().await;
+            if s.id() == id || s.tunnels_through(id) {
+                affected.push((s.depth(), session.clone()));
+            }
+        }
+        if affected.is_empty() {
+            return Ok(false);
+        }
+
+        affected.sort_by_key(|(depth, _)| std::cmp::Reverse(*depth));
+        let mut failure = None;
+        for (_, session) in affected {
+            if let Err(e) = session.write().await.disconnect().await {
+                failure.get_or_insert(e);
+            }
+        }
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(true),
+        }
+    }
+
+    /// Disconnects then forgets a session, along with the sessions tunneled through it.
+    /// They are forgotten even if disconnecting fails.
+    pub async fn remove_session(&self, id: usize) -> Result<bool> {
+        let disconnected = self.disconnect_session(id).await;
+        if let Ok(false) = disconnected {
+            return Ok(false);
+        }
+        let mut sessions = self.sessions.lock().await;
+        let mut kept = Vec::with_capacity(sessions.len());
+        for session in sessions.drain(..) {
+            let s = session.read().await;
+            if s.id() != id && !s.tunnels_through(id) {
+                drop(s);
+                kept.push(session);
+            }
+        }
+        *sessions = kept;
+        disconnected
+    }
+
+    pub async fn get_hosts(&self) -> Vec<HostDefinition> {
+        self.inventory.lock().await.hosts().to_vec()
+    }
+
+    pub async fn get_host(&self, host_id: usize) -> Option<HostDefinition> {
+        self.inventory.lock().await.get(host_id).cloned()
+    }
+
+    pub async fn save_host(&self, host: HostDefinition) -> Result<usize> {
+        self.inventory.lock().await.save_host(host)
+    }
+
+    pub async fn remove_host(&self, host_id: usize) -> Result<bool> {
+        self.inventory.lock().await.remove_host(host_id)
+    }
+
+    pub fn vault(&self) -> &Mutex<Vault> {
+        &self.vault
+    }
+
+    pub async fn resolve_secret(&self, secret: Option<Secret>) -> Result<Option<String>> {
+        Ok(match secret {
+            Some(Secret::Plain(secret)) => Some(secret),
+            Some(Secret::Vault(entry)) => Some(self.vault.lock().await.get(&entry)?),
+            None => None,
+        })
+    }
+
+    /// Without an explicit secret, the host's vault entry is used if it has one
+    pub async fn connect_host(
+        &self,
+        app: tauri::AppHandle,
+        host: &HostDefinition,
+        secret: Option<Secret>,
+    ) -> Result<usize> {
+        let secret = secret.or(host.vault_entry().map(|entry| Secret::Vault(entry.to_string())));
+        let auth = host.auth().with_secret(self.resolve_secret(secret).await?)?;
+        let context = self.connect_context(app.clone());
+        let addrs = host.host().clone().into();
+        let user = host.user().to_string();
+        let mut session = match host.jump_host() {
+            Some(jump_id) => {
+                let jump = self
+                    .find_host_session(jump_id)
+                    .await
+                    .ok_or(anyhow::anyhow!("Jump host for {} is not connected", host.host().address()))?;
+                Session::connect_via(jump, addrs, user, auth, context).await?
+            }
+            None => Session::connect(addrs, user, auth, context).await?,
+        };
+        session.set_host_id(host.id());
+        let id = session.id();
+        self.add_session(app, session).await;
+        Ok(id)
+    }
+
+    /// Connects the saved hosts flagged for it that aren't connected yet, as long as
+    /// their secret doesn't have to be asked for. Failures are sent as `auto-connect-failed`.
+    /// Host key and keyboard-interactive prompts are left to the UI, so it must be listening.
+    pub async fn auto_connect(&self, app: tauri::AppHandle) {
+        let vault_unlocked = self.vault.lock().await.is_unlocked();
+        let mut pending: Vec<HostDefinition> = self
+            .get_hosts()
+            .await
+            .into_iter()
+            .filter(|h| h.auto_connect())
+            .filter(|h| !h.auth().needs_secret() || (vault_unlocked && h.vault_entry().is_some()))
+            .collect();
+
+        // Jump hosts have to be up before whatever sits behind them
+        loop {
+            let count = pending.len();
+            let mut waiting = Vec::new();
+            for host in pending {
+                if self.find_host_session(host.id()).await.is_some() {
+                    continue;
+                }
+                if let Some(jump_id) = host.jump_host() {
+                    if self.find_host_session(jump_id).await.is_none() {
+                        waiting.push(host);
+                        continue;
+                    }
+                }
+                if let Err(e) = self.connect_host(app.clone(), &host, None).await {
+                    let failed = AutoConnectFailed { host_id: host.id(), error: format!("{:#}", e) };
+                    let _ = app.emit_all("auto-connect-failed", failed);
+                }
+            }
+            if waiting.is_empty() || waiting.len() == count {
+                break;
+            }
+            pending = waiting;
+        }
+    }
+
+    pub fn host_key_prompts(&self) -> &PendingPrompts<bool> {
+        &self.host_key_prompts
+    }
+
+    pub fn keyboard_interactive_prompts(&self) -> &PendingPrompts<Option<Vec<String>>> {
+        &self.keyboard_interactive_prompts
+    }
+
+    pub fn running_commands(&self) -> &RunningCommands {
+        &self.running_commands
+    }
 }
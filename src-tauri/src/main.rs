@@ -6,11 +6,21 @@ extern crate core;
 
 mod app;
 mod ssh;
+mod vault;
 
-use app::commands::{get_interfaces, get_sessions, start_session};
+use app::commands::{
+    answer_host_key, answer_keyboard_interactive, cancel_command, close_terminal, connect_host,
+    disconnect_session, frontend_ready, get_config_hosts, get_forwards, get_hosts, get_interfaces,
+    get_sessions, get_traffic_counters, get_vault_entries, lock_vault, open_terminal, remove_host,
+    remove_session, remove_vault_entry, resize_terminal, run_command, save_host, set_vault_entry,
+    sftp_download, sftp_list_dir, sftp_mkdir, sftp_read_file, sftp_remove, sftp_rename, sftp_stat,
+    sftp_upload, sftp_write_file, start_forward, start_session, stop_forward, unlock_vault,
+    vault_status, watch_throughput, write_terminal,
+};
 use app::AppState;
 
 use anyhow::{Context, Result};
+use tauri::Manager;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -19,10 +29,53 @@ async fn main() -> Result<()> {
     tauri::Builder::default()
         .invoke_handler(tauri::generate_handler![
             start_session,
+            disconnect_session,
+            remove_session,
+            get_hosts,
+            save_host,
+            remove_host,
+            connect_host,
+            frontend_ready,
+            answer_host_key,
+            answer_keyboard_interactive,
+            vault_status,
+            unlock_vault,
+            lock_vault,
+            get_vault_entries,
+            set_vault_entry,
+            remove_vault_entry,
             get_sessions,
-            get_interfaces
+            get_config_hosts,
+            get_interfaces,
+            get_traffic_counters,
+            watch_throughput,
+            run_command,
+            cancel_command,
+            open_terminal,
+            write_terminal,
+            resize_terminal,
+            close_terminal,
+            sftp_list_dir,
+            sftp_stat,
+            sftp_read_file,
+            sftp_write_file,
+            sftp_mkdir,
+            sftp_rename,
+            sftp_remove,
+            sftp_download,
+            sftp_upload,
+            get_forwards,
+            start_forward,
+            stop_forward
         ])
-        .manage(AppState::default())
+        .setup(|app| {
+            let data_dir = app
+                .path_resolver()
+                .app_data_dir()
+                .context("could not resolve the app data directory")?;
+            app.manage(AppState::new(data_dir)?);
+            Ok(())
+        })
         .run(tauri::generate_context!())
         .context("error while running tauri application")?;
 
@@ -0,0 +1,188 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::{Context, Result};
+use russh::client::{self, KeyboardInteractiveAuthResponse};
+use russh_keys::agent::client::AgentClient;
+use serde::{Deserialize, Serialize};
+
+use super::client::{Client, Prompter};
+
+#[derive(Clone)]
+pub enum AuthMethod {
+    Password { password: String },
+    PublicKey { key_path: PathBuf, passphrase: Option<String> },
+    Agent,
+    KeyboardInteractive,
+}
+
+impl AuthMethod {
+    /// Also what sudo gets asked with, sessions opened another way have none to give it
+    pub fn password(&self) -> Option<&str> {
+        match self {
+            AuthMethod::Password { password } => Some(password),
+            _ => None,
+        }
+    }
+}
+
+/// The server turned down the credentials we offered
+#[derive(Debug)]
+pub struct AuthRejected;
+
+impl std::fmt::Display for AuthRejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Authentication failed")
+    }
+}
+
+impl std::error::Error for AuthRejected {}
+
+/// `AuthMethod` without its secrets, this is what gets written to disk
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "method", rename_all = "camelCase")]
+pub enum AuthKind {
+    Password,
+    #[serde(rename_all = "camelCase")]
+    PublicKey { key_path: PathBuf },
+    Agent,
+    KeyboardInteractive,
+}
+
+impl From<&AuthMethod> for AuthKind {
+    fn from(auth: &AuthMethod) -> Self {
+        match auth {
+            AuthMethod::Password { .. } => AuthKind::Password,
+            AuthMethod::PublicKey { key_path, .. } => AuthKind::PublicKey { key_path: key_path.clone() },
+            AuthMethod::Agent => AuthKind::Agent,
+            AuthMethod::KeyboardInteractive => AuthKind::KeyboardInteractive,
+        }
+    }
+}
+
+impl AuthKind {
+    /// Whether we can't connect without asking for something first
+    pub fn needs_secret(&self) -> bool {
+        matches!(self, AuthKind::Password)
+    }
+
+    /// The secret is the password, or the key passphrase for encrypted keys
+    pub fn with_secret(&self, secret: Option<String>) -> Result<AuthMethod> {
+        Ok(match self {
+            AuthKind::Password => AuthMethod::Password {
+                password: secret.ok_or(anyhow::anyhow!("A password is required"))?,
+            },
+            AuthKind::PublicKey { key_path } => AuthMethod::PublicKey {
+                key_path: key_path.clone(),
+                passphrase: secret,
+            },
+            AuthKind::Agent => AuthMethod::Agent,
+            AuthKind::KeyboardInteractive => AuthMethod::KeyboardInteractive,
+        })
+    }
+}
+
+/// One round of a keyboard-interactive exchange, answered by the user
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyboardInteractivePrompt {
+    host: String,
+    user: String,
+    name: String,
+    instructions: String,
+    prompts: Vec<PromptField>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct PromptField {
+    prompt: String,
+    echo: bool,
+}
+
+impl AuthMethod {
+    pub async fn authenticate(
+        &self,
+        session: &mut client::Handle<Client>,
+        host: &str,
+        user: &str,
+        prompter: &dyn Prompter,
+    ) -> Result<bool> {
+        match self {
+            AuthMethod::Password { password } => {
+                Ok(session.authenticate_password(user, password).await?)
+            }
+            AuthMethod::PublicKey { key_path, passphrase } => {
+                let key_path = expand_home(key_path);
+                let key = russh_keys::load_secret_key(&key_path, passphrase.as_deref())
+                    .with_context(|| format!("Failed to load key '{}'", key_path.display()))?;
+                Ok(session.authenticate_publickey(user, Arc::new(key)).await?)
+            }
+            AuthMethod::Agent => {
+                let mut agent = AgentClient::connect_env()
+                    .await
+                    .context("Failed to connect to the SSH agent")?;
+                let identities = agent.request_identities().await?;
+                // A key the agent fails to sign with is skipped like a rejected one,
+                // its error is only worth reporting if no key got an answer from the server
+                let mut last_error = None;
+                let mut rejected = false;
+                for key in identities {
+                    let (returned_agent, res) = session.authenticate_future(user, key, agent).await;
+                    agent = returned_agent;
+                    match res {
+                        Ok(true) => return Ok(true),
+                        Ok(false) => rejected = true,
+                        Err(e) => last_error = Some(e),
+                    }
+                }
+                match last_error {
+                    Some(e) if !rejected => Err(e.into()),
+                    _ => Ok(false),
+                }
+            }
+            AuthMethod::KeyboardInteractive => {
+                let mut response = session
+                    .authenticate_keyboard_interactive_start(user, None)
+                    .await?;
+                loop {
+                    let (name, instructions, prompts) = match response {
+                        KeyboardInteractiveAuthResponse::Success => return Ok(true),
+                        KeyboardInteractiveAuthResponse::Failure => return Ok(false),
+                        KeyboardInteractiveAuthResponse::InfoRequest { name, instructions, prompts } => {
+                            (name, instructions, prompts)
+                        }
+                    };
+                    // Servers are allowed to send empty rounds, no need to bother the user with those
+                    let answers = if prompts.is_empty() {
+                        Vec::new()
+                    } else {
+                        let prompt = KeyboardInteractivePrompt {
+                            host: host.to_string(),
+                            user: user.to_string(),
+                            name,
+                            instructions,
+                            prompts: prompts
+                                .into_iter()
+                                .map(|p| PromptField { prompt: p.prompt, echo: p.echo })
+                                .collect(),
+                        };
+                        prompter
+                            .keyboard_interactive(prompt)
+                            .await
+                            .ok_or(anyhow::anyhow!("Keyboard-interactive authentication was cancelled"))?
+                    };
+                    response = session
+                        .authenticate_keyboard_interactive_respond(answers)
+                        .await?;
+                }
+            }
+        }
+    }
+}
+
+/// Expands a leading `~` to the user's home directory
+pub fn expand_home(path: &std::path::Path) -> PathBuf {
+    match (path.strip_prefix("~"), tauri::api::path::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
@@ -1,6 +1,41 @@
-use russh::client;
+use std::sync::Arc;
 
-pub struct Client {}
+use russh::{client, Channel};
+
+use super::{
+    auth::KeyboardInteractivePrompt,
+    forward::RemoteForwards,
+    known_hosts::{self, HostKeyError, HostKeyPrompt, HostKeyStatus, KnownHosts},
+};
+
+/// Asks the user to make decisions the SSH exchange can't make on its own
+#[async_trait::async_trait]
+pub trait Prompter: Send + Sync {
+    async fn confirm_host_key(&self, prompt: HostKeyPrompt) -> bool;
+
+    /// Returns one answer per prompt, or `None` if the user gave up
+    async fn keyboard_interactive(&self, prompt: KeyboardInteractivePrompt) -> Option<Vec<String>>;
+}
+
+/// Everything a connection needs besides the address and credentials
+#[derive(Clone)]
+pub struct ConnectContext {
+    pub known_hosts: Arc<KnownHosts>,
+    pub prompter: Arc<dyn Prompter>,
+}
+
+pub struct Client {
+    host: String,
+    port: u16,
+    context: ConnectContext,
+    forwards: RemoteForwards,
+}
+
+impl Client {
+    pub fn new(host: String, port: u16, context: ConnectContext, forwards: RemoteForwards) -> Self {
+        Self { host, port, context, forwards }
+    }
+}
 
 #[async_trait::async_trait]
 impl client::Handler for Client {
@@ -8,8 +43,44 @@ impl client::Handler for Client {
 
     async fn check_server_key(
         &mut self,
-        _server_public_key: &russh_keys::key::PublicKey,
+        server_public_key: &russh_keys::key::PublicKey,
     ) -> Result<bool, Self::Error> {
-        Ok(true)
+        let known_hosts = &self.context.known_hosts;
+        match known_hosts.check(&self.host, self.port, server_public_key)? {
+            HostKeyStatus::Known => Ok(true),
+            HostKeyStatus::Changed { path, line } => Err(HostKeyError::Changed {
+                host: self.host.clone(),
+                port: self.port,
+                fingerprint: known_hosts::fingerprint(server_public_key),
+                path,
+                line,
+            }.into()),
+            HostKeyStatus::Revoked { .. } => Err(HostKeyError::Revoked {
+                host: self.host.clone(),
+                port: self.port,
+                fingerprint: known_hosts::fingerprint(server_public_key),
+            }.into()),
+            HostKeyStatus::Unknown => {
+                let prompt = HostKeyPrompt::new(&self.host, self.port, server_public_key);
+                if !self.context.prompter.confirm_host_key(prompt).await {
+                    return Err(HostKeyError::Rejected { host: self.host.clone(), port: self.port }.into());
+                }
+                known_hosts.learn(&self.host, self.port, server_public_key)?;
+                Ok(true)
+            }
+        }
+    }
+
+    async fn server_channel_open_forwarded_tcpip(
+        &mut self,
+        channel: Channel<client::Msg>,
+        connected_address: &str,
+        connected_port: u32,
+        _originator_address: &str,
+        _originator_port: u32,
+        _session: &mut client::Session,
+    ) -> Result<(), Self::Error> {
+        self.forwards.accept(channel, connected_address, connected_port);
+        Ok(())
     }
 }
@@ -1,28 +1,205 @@
-use super::client::Client;
+use std::{
+    any::TypeId,
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use super::{
+    client::Client,
+    elevation::{self, Elevation},
+    facts::HostFacts,
+    shell::ShellCommand,
+    terminal::Utf8Decoder,
+};
 use anyhow::Result;
-use russh::{client::Handle, ChannelMsg};
+use russh::{
+    client::{self, Handle},
+    Channel, ChannelMsg, Sig,
+};
+use serde::Serialize;
+use tokio::sync::{watch, RwLock};
+
+/// How long a command may run when neither it nor the executor says otherwise
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+/// How much of stderr is still kept when it is streamed, enough for a sudo refusal
+const STREAMED_STDERR_TAIL: usize = 4096;
+
+#[derive(Debug)]
+pub enum CommandError {
+    NoSuitableImplementation,
+    /// The command ran but did not exit successfully
+    Failed { command: String, output: CommandOutput },
+    TimedOut { command: String, timeout: Duration },
+    Cancelled { command: String },
+    /// sudo or doas wouldn't run the command as root, or there is no way to ask them to
+    ElevationDenied { command: String, reason: String },
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CommandError::NoSuitableImplementation => write!(f, "No suitable implementation found"),
+            CommandError::Failed { command, output } => {
+                match (output.exit_status, &output.exit_signal) {
+                    (_, Some(signal)) => write!(f, "Command '{}' was killed by SIG{}", command, signal),
+                    _ if output.is_not_found() => write!(f, "Command '{}' was not found", command),
+                    _ if output.is_permission_denied() => {
+                        write!(f, "Permission denied running '{}'", command)
+                    }
+                    (Some(status), _) => {
+                        write!(f, "Command '{}' failed with exit status {}", command, status)
+                    }
+                    (None, None) => write!(f, "Command '{}' exited without a status", command),
+                }?;
+                match output.stderr.trim() {
+                    "" => Ok(()),
+                    stderr => write!(f, ": {}", stderr),
+                }
+            }
+            CommandError::TimedOut { command, timeout } => {
+                write!(f, "Command '{}' timed out after {}s", command, timeout.as_secs_f32())
+            }
+            CommandError::Cancelled { command } => write!(f, "Command '{}' was cancelled", command),
+            CommandError::ElevationDenied { command, reason } => {
+                write!(f, "Could not run '{}' as root: {}", command, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+/// Everything a remote command left behind
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandOutput {
+    /// Left empty when the output was streamed
+    pub stdout: String,
+    /// Only the end of it when the output was streamed
+    pub stderr: String,
+    /// Missing when the command was killed by a signal
+    pub exit_status: Option<u32>,
+    pub exit_signal: Option<String>,
+    pub duration: Duration,
+}
+
+impl CommandOutput {
+    pub fn success(&self) -> bool {
+        self.exit_status == Some(0)
+    }
+
+    /// What POSIX shells report for a missing executable
+    pub fn is_not_found(&self) -> bool {
+        self.exit_status == Some(127)
+    }
+
+    pub fn is_permission_denied(&self) -> bool {
+        let denied = ["Permission denied", "Operation not permitted"];
+        self.exit_status == Some(126)
+            || (!self.success() && denied.iter().any(|message| self.stderr.contains(message)))
+    }
+}
 
 pub trait VirtualCommand<T: 'static, const N: usize> {
     fn implementations(&self) -> [&'static dyn ConcreteCommand<T>; N];
 
-    async fn execute(&self, executor: &impl CommandExecutor) -> Result<T> {
-        for implementation in self.implementations() {
-            if implementation.detect(executor).await? {
-                return implementation.execute(executor).await;
+    /// Uses the first implementation, in order, whose probe succeeds.
+    /// The winner is remembered if the executor has a detection cache,
+    /// and detected again if it fails later on, the host may have changed since.
+    /// A probe that errors rules its implementation out, unless the run was cancelled.
+    ///
+    /// The cache is keyed by type, so `implementations()` must not depend on `&self`.
+    async fn execute(&self, executor: &impl CommandExecutor) -> Result<T>
+    where
+        Self: 'static,
+    {
+        let implementations = self.implementations();
+        let cache = executor.detection_cache();
+        let key = TypeId::of::<Self>();
+        if let (Some(cache), Some(index)) = (cache, cache.and_then(|cache| cache.get(key))) {
+            match implementations[index].execute(executor).await {
+                Err(e) if matches!(e.downcast_ref(), Some(CommandError::Failed { .. })) => cache.remove(key),
+                result => return result,
             }
         }
-        Err(anyhow::anyhow!("No suitable implementation found"))
+
+        // The probes are independent, waiting on each in turn only adds round-trips
+        let facts = executor.host_facts();
+        let probes = implementations.iter().map(|implementation| async move {
+            match facts.and_then(|facts| implementation.supports(facts)) {
+                Some(supported) => Ok(supported),
+                None => implementation.detect(executor).await,
+            }
+        });
+        for (index, detected) in futures::future::join_all(probes).await.into_iter().enumerate() {
+            match detected {
+                Ok(true) => {
+                    if let Some(cache) = cache {
+                        cache.set(key, index);
+                    }
+                    return implementations[index].execute(executor).await;
+                }
+                Err(e) if matches!(e.downcast_ref(), Some(CommandError::Cancelled { .. })) => return Err(e),
+                // A probe that timed out or could not run counts as not detected
+                Ok(false) | Err(_) => {}
+            }
+        }
+        Err(CommandError::NoSuitableImplementation.into())
+    }
+}
+
+/// Which implementation of each virtual command a host turned out to support
+#[derive(Default)]
+pub struct DetectionCache {
+    detected: Mutex<HashMap<TypeId, usize>>,
+}
+
+impl DetectionCache {
+    fn get(&self, command: TypeId) -> Option<usize> {
+        self.detected.lock().unwrap().get(&command).copied()
+    }
+
+    pub(super) fn set(&self, command: TypeId, implementation: usize) {
+        self.detected.lock().unwrap().insert(command, implementation);
+    }
+
+    fn remove(&self, command: TypeId) {
+        self.detected.lock().unwrap().remove(&command);
+    }
+
+    /// For when the host may have changed, like after a reconnect
+    pub fn clear(&self) {
+        self.detected.lock().unwrap().clear();
     }
 }
 
-pub trait ConcreteCommand<T> {
-    fn detection_command(&self) -> CommandString;
+pub trait ConcreteCommand<T>: Sync {
+    fn detection_command(&self) -> ShellCommand;
 
-    fn execution_command(&self) -> CommandString;
+    fn execution_command(&self) -> ShellCommand;
 
-    fn parse_detection_output(&self, output: &str) -> Result<bool>;
+    /// Gets the whole output, a probe failing is usually the answer
+    fn parse_detection_output(&self, output: &CommandOutput) -> Result<bool>;
 
+    /// Only called with the stdout of a successful run
     fn parse_execution_output(&self, output: &str) -> Result<T>;
+
+    /// Decides from what is known about the host, without probing.
+    /// `None` when the facts don't tell, the detection command is run then.
+    fn supports(&self, _facts: &HostFacts) -> Option<bool> {
+        None
+    }
+
+    /// Execution is done as root, detection still isn't
+    fn requires_root(&self) -> bool {
+        false
+    }
+
+    /// Overrides the executor's default, for commands known to be slow
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
 }
 
 impl<T> dyn ConcreteCommand<T>
@@ -30,102 +207,455 @@ where
     T: Sized,
 {
     async fn detect(&self, executor: &impl CommandExecutor) -> Result<bool> {
-        let detection_output = executor.execute(self.detection_command().as_str()).await?;
+        let detection_output = executor
+            .execute_with_timeout(self.detection_command().as_str(), self.timeout_for(executor))
+            .await?;
         self.parse_detection_output(&detection_output)
     }
 
     async fn execute(&self, executor: &impl CommandExecutor) -> Result<T> {
-        let output = executor.execute(self.execution_command().as_str()).await?;
-        self.parse_execution_output(&output)
+        let command = self.execution_command();
+        let timeout = self.timeout_for(executor);
+        let output = match self.requires_root() {
+            true => executor.execute_elevated(command.as_str(), timeout).await?,
+            false => executor.execute_with_timeout(command.as_str(), timeout).await?,
+        };
+        if !output.success() {
+            return Err(CommandError::Failed { command: command.as_str().to_string(), output }.into());
+        }
+        self.parse_execution_output(&output.stdout)
+    }
+
+    fn timeout_for(&self, executor: &impl CommandExecutor) -> Duration {
+        self.timeout().unwrap_or(executor.default_timeout())
     }
 }
 
-pub enum CommandString {
-    Static(&'static str),
-    Dynamic(String),
+pub trait CommandExecutor {
+    fn default_timeout(&self) -> Duration {
+        DEFAULT_TIMEOUT
+    }
+
+    /// Where virtual commands remember their detected implementation, none by default
+    fn detection_cache(&self) -> Option<&DetectionCache> {
+        None
+    }
+
+    /// What the host is known to be, if it was fingerprinted
+    fn host_facts(&self) -> Option<&HostFacts> {
+        None
+    }
+
+    /// Only fails if the command could not be run or didn't finish in time,
+    /// a non-zero exit is still an output
+    async fn execute_with_timeout(&self, command: &str, timeout: Duration) -> Result<CommandOutput>;
+
+    /// Like `execute_with_timeout` but as root. Refusals are reported as `CommandError::ElevationDenied`.
+    async fn execute_elevated(&self, command: &str, _timeout: Duration) -> Result<CommandOutput> {
+        Err(CommandError::ElevationDenied {
+            command: command.to_string(),
+            reason: "this executor can't elevate".to_string(),
+        }
+        .into())
+    }
 }
 
-impl CommandString {
-    fn as_str(&self) -> &str {
-        match self {
-            CommandString::Static(s) => s,
-            CommandString::Dynamic(s) => s.as_str(),
+/// A piece of output, as soon as the server sent it
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "stream", content = "data", rename_all = "camelCase")]
+pub enum OutputChunk {
+    Stdout(String),
+    Stderr(String),
+}
+
+/// For long-running commands whose output is wanted as it comes
+pub trait StreamingCommandExecutor: CommandExecutor {
+    /// Hands every chunk to `on_chunk` instead of collecting them, only the end of stderr is kept.
+    /// Without a timeout the command runs until it exits or is cancelled.
+    async fn execute_streaming(
+        &self,
+        command: &str,
+        timeout: Option<Duration>,
+        on_chunk: &mut (dyn FnMut(OutputChunk) + Send),
+    ) -> Result<CommandOutput>;
+}
+
+pub struct SshCommandExecutor<'a> {
+    handle: &'a RwLock<Handle<Client>>,
+    timeout: Duration,
+    cancel: Option<watch::Receiver<bool>>,
+    detection_cache: Option<&'a DetectionCache>,
+    host_facts: Option<&'a HostFacts>,
+    elevation: Option<&'a Elevation>,
+    /// Run every command as root, not only those asking for it
+    elevate_all: bool,
+}
+
+impl<'a> SshCommandExecutor<'a> {
+    pub fn new(handle: &'a RwLock<Handle<Client>>) -> Self {
+        Self {
+            handle,
+            timeout: DEFAULT_TIMEOUT,
+            cancel: None,
+            detection_cache: None,
+            host_facts: None,
+            elevation: None,
+            elevate_all: false,
+        }
+    }
+
+    pub fn with_detection_cache(mut self, detection_cache: &'a DetectionCache) -> Self {
+        self.detection_cache = Some(detection_cache);
+        self
+    }
+
+    pub fn with_host_facts(mut self, host_facts: &'a HostFacts) -> Self {
+        self.host_facts = Some(host_facts);
+        self
+    }
+
+    /// Lets commands be run as root
+    pub fn with_elevation(mut self, elevation: &'a Elevation) -> Self {
+        self.elevation = Some(elevation);
+        self
+    }
+
+    /// Every command run by this executor is run as root, needs `with_elevation`
+    pub fn elevated(mut self) -> Self {
+        self.elevate_all = true;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Every command run by this executor is aborted once `cancel` turns true
+    pub fn with_cancel(mut self, cancel: watch::Receiver<bool>) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    async fn cancelled(&self) {
+        if let Some(mut cancel) = self.cancel.clone() {
+            if cancel.wait_for(|cancelled| *cancelled).await.is_ok() {
+                return;
+            }
         }
+        // No way to be cancelled anymore
+        std::future::pending().await
+    }
+
+    async fn run(
+        &self,
+        command: &str,
+        timeout: Option<Duration>,
+        on_chunk: Option<&mut (dyn FnMut(OutputChunk) + Send)>,
+        elevated: bool,
+    ) -> Result<CommandOutput> {
+        if !(elevated || self.elevate_all) {
+            return self.run_as_is(command, None, timeout, on_chunk).await;
+        }
+        let Some(elevation) = self.elevation else {
+            return Err(CommandError::ElevationDenied {
+                command: command.to_string(),
+                reason: "elevation isn't available on this session".to_string(),
+            }
+            .into());
+        };
+        let probe = |probe| self.run_as_is(probe, None, Some(self.timeout), None);
+        let elevated = elevation.prepare(command, probe).await?;
+        let stdin = elevated.stdin.as_deref().map(String::as_str);
+        let output = self.run_as_is(elevated.command.as_str(), stdin, timeout, on_chunk).await?;
+        match elevation::denial(&output) {
+            Some(reason) => Err(CommandError::ElevationDenied { command: command.to_string(), reason }.into()),
+            None => Ok(output),
+        }
+    }
+
+    async fn run_as_is(
+        &self,
+        command: &str,
+        stdin: Option<&str>,
+        timeout: Option<Duration>,
+        on_chunk: Option<&mut (dyn FnMut(OutputChunk) + Send)>,
+    ) -> Result<CommandOutput> {
+        let mut channel = None;
+        // Opening counts against the timeout too, a busy server can be slow to answer
+        let run = async {
+            // Only held while the channel opens, a `-R` request may be waiting on it
+            let opened = self.handle.read().await.channel_open_session().await?;
+            read_output(channel.insert(opened), command, stdin, on_chunk).await
+        };
+        let timed_out = async {
+            match timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => std::future::pending().await,
+            }
+        };
+        let result = tokio::select! {
+            output = run => return output,
+            _ = timed_out => CommandError::TimedOut {
+                command: command.to_string(),
+                timeout: timeout.unwrap_or_default(),
+            },
+            _ = self.cancelled() => CommandError::Cancelled { command: command.to_string() },
+        };
+        // Hanging up is all we can do, the server decides what happens to the process
+        if let Some(channel) = channel {
+            let _ = channel.close().await;
+        }
+        Err(result.into())
     }
 }
 
-pub trait CommandExecutor {
-    async fn execute(&self, command: &str) -> Result<String>;
+impl CommandExecutor for SshCommandExecutor<'_> {
+    fn default_timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn detection_cache(&self) -> Option<&DetectionCache> {
+        self.detection_cache
+    }
+
+    fn host_facts(&self) -> Option<&HostFacts> {
+        self.host_facts
+    }
+
+    async fn execute_with_timeout(&self, command: &str, timeout: Duration) -> Result<CommandOutput> {
+        self.run(command, Some(timeout), None, false).await
+    }
+
+    async fn execute_elevated(&self, command: &str, timeout: Duration) -> Result<CommandOutput> {
+        self.run(command, Some(timeout), None, true).await
+    }
 }
 
-struct SshCommandExecutor {
-    handle: Handle<Client>,
+impl StreamingCommandExecutor for SshCommandExecutor<'_> {
+    async fn execute_streaming(
+        &self,
+        command: &str,
+        timeout: Option<Duration>,
+        on_chunk: &mut (dyn FnMut(OutputChunk) + Send),
+    ) -> Result<CommandOutput> {
+        self.run(command, timeout, Some(on_chunk), false).await
+    }
 }
 
-impl SshCommandExecutor {
-    pub fn new(handle: Handle<Client>) -> Self {
-        Self { handle }
+/// Output is collected unless `on_chunk` is there to take it.
+/// Stdin is closed after `stdin` is written, if there is one.
+async fn read_output(
+    channel: &mut Channel<client::Msg>,
+    command: &str,
+    stdin: Option<&str>,
+    on_chunk: Option<&mut (dyn FnMut(OutputChunk) + Send)>,
+) -> Result<CommandOutput> {
+    let start = Instant::now();
+    channel.exec(true, command).await?;
+    if let Some(stdin) = stdin {
+        channel.data(stdin.as_bytes()).await?;
+        channel.eof().await?;
     }
+
+    let mut collector = OutputCollector::new(on_chunk);
+    // The exit status can come before the last of the data, read until the channel closes
+    while let Some(msg) = channel.wait().await {
+        collector.receive(msg);
+    }
+    Ok(collector.finish(start.elapsed()))
 }
 
-impl CommandExecutor for SshCommandExecutor {
-    async fn execute(&self, command: &str) -> Result<String> {
-        let mut channel = self.handle.channel_open_session().await?;
-        channel.exec(true, command).await?;
+/// Gathers what a command sends back, or hands it to `on_chunk` as it comes
+struct OutputCollector<'a> {
+    on_chunk: Option<&'a mut (dyn FnMut(OutputChunk) + Send)>,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    /// The end of stderr when streaming, sudo and doas refusals are found in it
+    stderr_tail: String,
+    exit_status: Option<u32>,
+    exit_signal: Option<String>,
+    // Streamed chunks can end in the middle of a character
+    stdout_decoder: Utf8Decoder,
+    stderr_decoder: Utf8Decoder,
+}
 
-        let mut buffer = Vec::new();
-        loop {
-            let msg = channel
-                .wait()
-                .await
-                .ok_or(anyhow::anyhow!("Channel closed"))?;
+impl<'a> OutputCollector<'a> {
+    fn new(on_chunk: Option<&'a mut (dyn FnMut(OutputChunk) + Send)>) -> Self {
+        Self {
+            on_chunk,
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            stderr_tail: String::new(),
+            exit_status: None,
+            exit_signal: None,
+            stdout_decoder: Utf8Decoder::default(),
+            stderr_decoder: Utf8Decoder::default(),
+        }
+    }
 
-            match msg {
-                ChannelMsg::Data { data } => {
-                    buffer.extend_from_slice(&data);
-                }
-                ChannelMsg::ExitStatus { exit_status } => {
-                    if exit_status != 0 {
-                        return Err(anyhow::anyhow!(
-                            "Command '{}' failed with exit status {}",
-                            command,
-                            exit_status
-                        ));
-                    }
-                    break;
-                }
-                msg => {
-                    println!("Unexpected message: {:?}", msg);
-                }
+    fn receive(&mut self, msg: ChannelMsg) {
+        match (msg, self.on_chunk.is_some()) {
+            (ChannelMsg::Data { data }, true) => {
+                let text = self.stdout_decoder.decode(&data);
+                self.send(OutputChunk::Stdout(text));
             }
+            (ChannelMsg::ExtendedData { data, ext: 1 }, true) => {
+                let text = self.stderr_decoder.decode(&data);
+                self.send(OutputChunk::Stderr(text));
+            }
+            (ChannelMsg::Data { data }, false) => self.stdout.extend_from_slice(&data),
+            (ChannelMsg::ExtendedData { data, ext: 1 }, false) => self.stderr.extend_from_slice(&data),
+            (ChannelMsg::ExitStatus { exit_status }, _) => self.exit_status = Some(exit_status),
+            (ChannelMsg::ExitSignal { signal_name, .. }, _) => {
+                self.exit_signal = Some(signal_name_of(signal_name))
+            }
+            _ => {}
         }
+    }
 
-        Ok(String::from_utf8(buffer)?)
+    /// Empty chunks are dropped
+    fn send(&mut self, chunk: OutputChunk) {
+        let Some(on_chunk) = self.on_chunk.as_mut() else {
+            return;
+        };
+        match &chunk {
+            OutputChunk::Stdout(text) | OutputChunk::Stderr(text) if text.is_empty() => return,
+            OutputChunk::Stderr(text) => keep_tail(&mut self.stderr_tail, text, STREAMED_STDERR_TAIL),
+            OutputChunk::Stdout(_) => {}
+        }
+        on_chunk(chunk);
+    }
+
+    fn finish(mut self, duration: Duration) -> CommandOutput {
+        let stderr = match self.on_chunk {
+            Some(_) => {
+                let (stdout, stderr) = (self.stdout_decoder.finish(), self.stderr_decoder.finish());
+                self.send(OutputChunk::Stdout(stdout));
+                self.send(OutputChunk::Stderr(stderr));
+                self.stderr_tail
+            }
+            None => String::from_utf8_lossy(&self.stderr).into_owned(),
+        };
+        CommandOutput {
+            stdout: String::from_utf8_lossy(&self.stdout).into_owned(),
+            stderr,
+            exit_status: self.exit_status,
+            exit_signal: self.exit_signal,
+            duration,
+        }
+    }
+}
+
+/// Appends `text`, then drops whole characters from the front until at most `max` bytes are left
+fn keep_tail(tail: &mut String, text: &str, max: usize) {
+    tail.push_str(text);
+    if tail.len() > max {
+        let mut start = tail.len() - max;
+        while !tail.is_char_boundary(start) {
+            start += 1;
+        }
+        tail.drain(..start);
+    }
+}
+
+fn signal_name_of(signal: Sig) -> String {
+    match signal {
+        Sig::Custom(name) => name,
+        signal => format!("{:?}", signal),
     }
 }
 
 #[cfg(test)]
 pub mod test {
-    use std::collections::HashMap;
-
     use super::*;
 
     pub struct MockCommandExecutor {
         mappings: HashMap<String, String>,
+        detection_cache: DetectionCache,
+        /// Every command run so far
+        pub history: Mutex<Vec<String>>,
     }
 
     impl MockCommandExecutor {
         pub fn new(mappings: HashMap<String, String>) -> Self {
-            Self { mappings }
+            Self { mappings, detection_cache: DetectionCache::default(), history: Mutex::default() }
         }
     }
 
     impl CommandExecutor for MockCommandExecutor {
-        async fn execute(&self, command: &str) -> Result<String> {
-            self.mappings
-                .get(command)
-                .cloned()
-                .ok_or(anyhow::anyhow!("Command not found"))
+        fn detection_cache(&self) -> Option<&DetectionCache> {
+            Some(&self.detection_cache)
+        }
+
+        /// Unmapped commands behave like a missing executable
+        async fn execute_with_timeout(&self, command: &str, _timeout: Duration) -> Result<CommandOutput> {
+            self.history.lock().unwrap().push(command.to_string());
+            let (stdout, stderr, exit_status) = match self.mappings.get(command) {
+                Some(stdout) => (stdout.clone(), String::new(), 0),
+                None => (String::new(), format!("sh: {}: command not found", command), 127),
+            };
+            Ok(CommandOutput {
+                stdout,
+                stderr,
+                exit_status: Some(exit_status),
+                exit_signal: None,
+                duration: Duration::ZERO,
+            })
         }
     }
+
+    #[test]
+    fn test_command_error_message() {
+        let output = |exit_status, stderr: &str| CommandOutput {
+            stdout: String::new(),
+            stderr: stderr.to_string(),
+            exit_status: Some(exit_status),
+            exit_signal: None,
+            duration: Duration::ZERO,
+        };
+        let failed = |output| CommandError::Failed { command: "ip a".to_string(), output }.to_string();
+
+        assert_eq!(
+            failed(output(127, "sh: ip: not found\n")),
+            "Command 'ip a' was not found: sh: ip: not found"
+        );
+        assert_eq!(
+            failed(output(1, "cat: /root/x: Permission denied")),
+            "Permission denied running 'ip a': cat: /root/x: Permission denied"
+        );
+        assert_eq!(failed(output(2, "")), "Command 'ip a' failed with exit status 2");
+        assert!(!output(0, "Permission denied").is_permission_denied());
+    }
+
+    #[test]
+    fn test_streamed_elevated_denial() {
+        let mut chunks = Vec::new();
+        let mut on_chunk = |chunk| chunks.push(chunk);
+        let mut collector = OutputCollector::new(Some(&mut on_chunk));
+        let data = |data: &str| russh::CryptoVec::from_slice(data.as_bytes());
+        let stderr = |text: &str| ChannelMsg::ExtendedData { data: data(text), ext: 1 };
+        collector.receive(ChannelMsg::Data { data: data("starting\n") });
+        collector.receive(stderr(&format!("{}\n", "x".repeat(STREAMED_STDERR_TAIL))));
+        collector.receive(stderr("sudo: a password"));
+        collector.receive(stderr(" is required\n"));
+        collector.receive(ChannelMsg::ExitStatus { exit_status: 1 });
+        let output = collector.finish(Duration::ZERO);
+
+        assert_eq!(output.stdout, "");
+        assert_eq!(output.stderr.len(), STREAMED_STDERR_TAIL);
+        assert_eq!(elevation::denial(&output).as_deref(), Some("sudo: a password is required"));
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks[0], OutputChunk::Stdout("starting\n".to_string()));
+    }
+
+    #[test]
+    fn test_keep_tail() {
+        let mut tail = String::new();
+        keep_tail(&mut tail, "abc", 4);
+        keep_tail(&mut tail, "dé", 4);
+        assert_eq!(tail, "cdé");
+        keep_tail(&mut tail, "f", 4);
+        assert_eq!(tail, "déf");
+    }
 }
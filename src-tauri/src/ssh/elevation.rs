@@ -0,0 +1,209 @@
+use std::future::Future;
+
+use anyhow::Result;
+use tokio::sync::OnceCell;
+use zeroize::Zeroizing;
+
+use super::{
+    command::{CommandError, CommandOutput},
+    facts::HostFacts,
+    shell::ShellCommand,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ElevationMethod {
+    Sudo,
+    Doas,
+}
+
+/// A command wrapped to run as root
+pub struct ElevatedCommand {
+    pub command: ShellCommand,
+    /// To be written before anything else, then stdin is closed
+    pub stdin: Option<Zeroizing<String>>,
+}
+
+/// How commands become root on a host, built for each connection from its facts and credentials
+pub struct Elevation {
+    method: Option<ElevationMethod>,
+    password: Option<Zeroizing<String>>,
+    /// Whether no password is asked, found out on first use
+    passwordless: OnceCell<bool>,
+}
+
+impl Elevation {
+    /// sudo is preferred since it can be given a password, and assumed when the facts are missing
+    pub fn new(facts: Option<&HostFacts>, password: Option<&str>) -> Self {
+        let method = match facts {
+            Some(facts) if !facts.has_sudo && facts.has_doas => Some(ElevationMethod::Doas),
+            Some(facts) if !facts.has_sudo => None,
+            _ => Some(ElevationMethod::Sudo),
+        };
+        Self {
+            method,
+            password: password.map(|password| Zeroizing::new(password.to_string())),
+            passwordless: OnceCell::new(),
+        }
+    }
+
+    /// `run` is used to find out whether a password is needed, the first time only
+    pub async fn prepare<F, Fut>(&self, command: &str, run: F) -> Result<ElevatedCommand>
+    where
+        F: FnOnce(&'static str) -> Fut,
+        Fut: Future<Output = Result<CommandOutput>>,
+    {
+        let denied = |reason: &str| CommandError::ElevationDenied {
+            command: command.to_string(),
+            reason: reason.to_string(),
+        };
+        let Some(method) = self.method else {
+            return Err(denied("neither sudo nor doas is installed").into());
+        };
+        let passwordless = *self
+            .passwordless
+            .get_or_try_init(|| async { Ok::<_, anyhow::Error>(run(probe_command(method)).await?.success()) })
+            .await?;
+
+        let wrap = |program, options: &[&str]| {
+            ShellCommand::new(program).args(options).args(["--", "sh", "-c", command])
+        };
+        let elevated = match (method, passwordless, &self.password) {
+            (ElevationMethod::Sudo, true, _) => ElevatedCommand {
+                command: wrap("sudo", &["-n"]),
+                stdin: None,
+            },
+            // -k so a cached login can't leave the password for the command to read
+            (ElevationMethod::Sudo, false, Some(password)) => ElevatedCommand {
+                command: wrap("sudo", &["-k", "-S", "-p", ""]),
+                stdin: Some(Zeroizing::new(format!("{}\n", password.as_str()))),
+            },
+            (ElevationMethod::Sudo, false, None) => {
+                return Err(denied(
+                    "sudo asks for a password, which is only known for sessions opened with password authentication",
+                )
+                .into())
+            }
+            (ElevationMethod::Doas, true, _) => ElevatedCommand {
+                command: wrap("doas", &["-n"]),
+                stdin: None,
+            },
+            (ElevationMethod::Doas, false, _) => {
+                return Err(denied("doas only reads passwords from a terminal, it needs a nopass rule").into())
+            }
+        };
+        Ok(elevated)
+    }
+}
+
+/// Succeeds only when no password is needed, a cached login doesn't count
+fn probe_command(method: ElevationMethod) -> &'static str {
+    match method {
+        ElevationMethod::Sudo => "sudo -k -n true",
+        ElevationMethod::Doas => "doas -n true",
+    }
+}
+
+/// What sudo prints when it refuses, after its `sudo: ` prefix
+const SUDO_REFUSALS: [&str; 7] = [
+    "a password is required",
+    "a terminal is required to read the password",
+    "no tty present and no askpass program specified",
+    "sorry, you must have a tty to run sudo",
+    "no password was provided",
+    "account validation failure",
+    "PAM authentication error",
+];
+
+/// What doas prints when it refuses, after its `doas: ` prefix
+const DOAS_REFUSALS: [&str; 4] =
+    ["Operation not permitted", "Authentication failed", "Authorization required", "a password is required"];
+
+/// Why sudo or doas refused to run the command, if they did. Only their own refusals count,
+/// the command itself can mention sudo or fail with the same status.
+pub fn denial(output: &CommandOutput) -> Option<String> {
+    let refused = |line: &str| match (line.strip_prefix("sudo: "), line.strip_prefix("doas: ")) {
+        (Some(message), _) => {
+            SUDO_REFUSALS.iter().any(|refusal| message.starts_with(refusal))
+                || message.contains("incorrect password attempt")
+        }
+        (_, Some(message)) => DOAS_REFUSALS.iter().any(|refusal| message.starts_with(refusal)),
+        _ => line.starts_with("Sorry, user ") || line.contains(" is not in the sudoers file"),
+    };
+    // The shell running the wrapper, not the command, couldn't find it
+    let missing = |line: &str| {
+        line.strip_suffix(": not found")
+            .or_else(|| line.strip_suffix(": command not found"))
+            .is_some_and(|program| program.ends_with("sudo") || program.ends_with("doas"))
+    };
+    let mut lines = output.stderr.lines().rev().map(str::trim);
+    match output.exit_status {
+        Some(1) => lines.find(|line| refused(line)),
+        Some(127) => lines.find(|line| missing(line)),
+        _ => None,
+    }
+    .map(str::to_string)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn output(exit_status: u32, stderr: &str) -> CommandOutput {
+        CommandOutput {
+            stdout: String::new(),
+            stderr: stderr.to_string(),
+            exit_status: Some(exit_status),
+            exit_signal: None,
+            duration: Duration::ZERO,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prepare() {
+        let nopasswd = Elevation::new(None, None);
+        let elevated = nopasswd
+            .prepare("cat /etc/shadow", |probe| async move {
+                assert_eq!(probe, "sudo -k -n true");
+                Ok(output(0, ""))
+            })
+            .await
+            .unwrap();
+        assert_eq!(elevated.command.as_str(), "sudo -n -- sh -c 'cat /etc/shadow'");
+        assert!(elevated.stdin.is_none());
+        // The probe result is kept
+        let elevated = nopasswd.prepare("echo 'hi'", |_| async { unreachable!() }).await.unwrap();
+        assert_eq!(elevated.command.as_str(), r"sudo -n -- sh -c 'echo '\''hi'\'''");
+
+        let password = Elevation::new(None, Some("hunter2"));
+        let needs_password = |_| async { Ok(output(1, "sudo: a password is required\n")) };
+        let elevated = password.prepare("dmidecode", needs_password).await.unwrap();
+        assert_eq!(elevated.command.as_str(), "sudo -k -S -p '' -- sh -c dmidecode");
+        assert_eq!(elevated.stdin.as_deref().map(String::as_str), Some("hunter2\n"));
+
+        let err = Elevation::new(None, None).prepare("dmidecode", needs_password).await.err().unwrap();
+        assert!(matches!(
+            err.downcast_ref::<CommandError>(),
+            Some(CommandError::ElevationDenied { .. })
+        ));
+    }
+
+    #[test]
+    fn test_denial() {
+        let wrong_password = "Sorry, try again.\nsudo: no password was provided\nsudo: 1 incorrect password attempt\n";
+        assert_eq!(denial(&output(1, wrong_password)).as_deref(), Some("sudo: 1 incorrect password attempt"));
+        assert_eq!(
+            denial(&output(1, "alice is not in the sudoers file.\n")).as_deref(),
+            Some("alice is not in the sudoers file.")
+        );
+        assert_eq!(denial(&output(1, "cat: /root/x: No such file or directory\n")), None);
+        assert_eq!(denial(&output(0, "sudo: unable to resolve host lab\n")), None);
+        assert_eq!(denial(&output(1, "sudo: unable to resolve host lab\ngrep: x: No such file\n")), None);
+        assert_eq!(denial(&output(1, "/etc/sudoers.d/lab: bad permissions, should be mode 0440\n")), None);
+        assert_eq!(denial(&output(1, "doas: Operation not permitted\n")).as_deref(), Some("doas: Operation not permitted"));
+
+        assert_eq!(denial(&output(127, "sh: 1: sudo: not found\n")).as_deref(), Some("sh: 1: sudo: not found"));
+        assert_eq!(denial(&output(127, "sh: 1: dmidecode: not found\n")), None);
+    }
+}
@@ -0,0 +1,314 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+use super::{command::CommandExecutor, shell::ShellCommand};
+
+/// Fingerprinting is a nicety, it shouldn't hold up connecting for long
+pub const FINGERPRINT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Everything is gathered in one round-trip, each probe prints its own section.
+/// Run through `sh` since the login shell could be anything.
+const FINGERPRINT_SCRIPT: &str = r#"
+echo "--- os-release"; cat /etc/os-release 2>/dev/null || cat /usr/lib/os-release 2>/dev/null
+echo "--- uname"; uname -s; uname -r; uname -m
+echo "--- init"; cat /proc/1/comm 2>/dev/null; [ -d /run/systemd/system ] && echo systemd; [ -d /run/openrc ] && echo openrc
+echo "--- shell"; echo "$SHELL"
+echo "--- userland"; ls --version 2>&1 | head -n 1; readlink -f "$(command -v ls)"
+echo "--- sudo"; command -v sudo
+echo "--- doas"; command -v doas
+echo "--- virt"; systemd-detect-virt --container 2>/dev/null; systemd-detect-virt --vm 2>/dev/null
+echo "--- container"; [ -e /.dockerenv ] && echo docker; [ -e /run/.containerenv ] && echo podman
+echo "--- dmi"; cat /sys/class/dmi/id/sys_vendor /sys/class/dmi/id/product_name 2>/dev/null
+true"#;
+
+/// What a host turned out to be, gathered once per connection
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HostFacts {
+    /// Missing on systems without an os-release file, like the BSDs
+    pub os: Option<OsRelease>,
+    /// `uname -s`, like `Linux` or `FreeBSD`
+    pub kernel: String,
+    pub kernel_release: String,
+    pub arch: String,
+    pub init: InitSystem,
+    /// The login shell
+    pub shell: Option<String>,
+    pub userland: Userland,
+    pub has_sudo: bool,
+    pub has_doas: bool,
+    pub virtualization: Virtualization,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OsRelease {
+    /// Like `debian` or `alpine`
+    pub id: String,
+    /// Distributions this one is derived from
+    pub id_like: Vec<String>,
+    pub name: Option<String>,
+    pub version_id: Option<String>,
+    pub pretty_name: Option<String>,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum InitSystem {
+    Systemd,
+    OpenRc,
+    Runit,
+    S6,
+    SysVinit,
+    /// Including containers, whose first process is usually the application
+    Unknown,
+}
+
+/// Which flavor of the basic tools is installed, their flags differ
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum Userland {
+    Gnu,
+    Busybox,
+    Bsd,
+    Unknown,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Virtualization {
+    BareMetal,
+    /// `technology` uses the names of `systemd-detect-virt`, like `docker` or `lxc`
+    Container { technology: String },
+    /// `technology` uses the names of `systemd-detect-virt`, like `kvm` or `vmware`
+    Vm { technology: String },
+    Unknown,
+}
+
+impl HostFacts {
+    pub async fn gather(executor: &impl CommandExecutor) -> Result<Self> {
+        let command = ShellCommand::script(FINGERPRINT_SCRIPT);
+        let output = executor
+            .execute_with_timeout(command.as_str(), executor.default_timeout())
+            .await?;
+        Self::parse(&output.stdout)
+    }
+
+    pub fn is_linux(&self) -> bool {
+        self.kernel == "Linux"
+    }
+
+    fn parse(output: &str) -> Result<Self> {
+        let sections = sections(output);
+        let section = |name| sections.get(name).map(Vec::as_slice).unwrap_or_default();
+        let [kernel, kernel_release, arch] = section("uname") else {
+            bail!("Unexpected fingerprint output: {}", output);
+        };
+
+        Ok(Self {
+            os: parse_os_release(section("os-release")),
+            kernel: kernel.to_string(),
+            kernel_release: kernel_release.to_string(),
+            arch: arch.to_string(),
+            init: parse_init(section("init")),
+            shell: section("shell").first().map(|shell| shell.to_string()),
+            userland: parse_userland(section("userland"), kernel),
+            has_sudo: !section("sudo").is_empty(),
+            has_doas: !section("doas").is_empty(),
+            virtualization: parse_virtualization(section("virt"), section("container"), section("dmi")),
+        })
+    }
+}
+
+/// Non-empty lines under each `--- name` marker
+fn sections(output: &str) -> HashMap<&str, Vec<&str>> {
+    let mut sections = HashMap::<_, Vec<_>>::new();
+    let mut current = None;
+    for line in output.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        match (line.strip_prefix("--- "), current) {
+            (Some(name), _) => current = Some(name),
+            (None, Some(name)) => sections.entry(name).or_default().push(line),
+            (None, None) => {}
+        }
+    }
+    sections
+}
+
+fn parse_os_release(lines: &[&str]) -> Option<OsRelease> {
+    let fields = lines
+        .iter()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key, unquote(value)))
+        .collect::<HashMap<_, _>>();
+    let field = |key| fields.get(key).cloned();
+    Some(OsRelease {
+        // The spec says to assume `linux` when there is no ID
+        id: field("ID").or(lines.first().map(|_| "linux".to_string()))?,
+        id_like: field("ID_LIKE")
+            .map(|id_like| id_like.split_whitespace().map(str::to_string).collect())
+            .unwrap_or_default(),
+        name: field("NAME"),
+        version_id: field("VERSION_ID"),
+        pretty_name: field("PRETTY_NAME"),
+    })
+}
+
+fn unquote(value: &str) -> String {
+    let unquoted = ['"', '\'']
+        .iter()
+        .find_map(|quote| value.strip_prefix(*quote).and_then(|value| value.strip_suffix(*quote)))
+        .unwrap_or(value);
+    unquoted.replace("\\\"", "\"").replace("\\$", "$").replace("\\\\", "\\")
+}
+
+/// The first line is the name of PID 1, the next are markers left by the init system
+fn parse_init(lines: &[&str]) -> InitSystem {
+    if lines.contains(&"systemd") {
+        return InitSystem::Systemd;
+    }
+    match lines.first() {
+        Some(&"openrc-init") => InitSystem::OpenRc,
+        Some(&"init") if lines.contains(&"openrc") => InitSystem::OpenRc,
+        Some(&"runit") | Some(&"runit-init") => InitSystem::Runit,
+        Some(&"s6-svscan") => InitSystem::S6,
+        Some(&"init") => InitSystem::SysVinit,
+        _ => InitSystem::Unknown,
+    }
+}
+
+fn parse_userland(lines: &[&str], kernel: &str) -> Userland {
+    if lines.iter().any(|line| line.contains("GNU coreutils")) {
+        Userland::Gnu
+    } else if lines.iter().any(|line| line.to_lowercase().contains("busybox")) {
+        Userland::Busybox
+    } else if kernel.ends_with("BSD") || kernel == "Darwin" {
+        Userland::Bsd
+    } else {
+        Userland::Unknown
+    }
+}
+
+fn parse_virtualization(virt: &[&str], container: &[&str], dmi: &[&str]) -> Virtualization {
+    // systemd-detect-virt knows best, it prints the container then the VM, "none" for either
+    if let [container, vm] = virt {
+        return match (*container, *vm) {
+            ("none", "none") => Virtualization::BareMetal,
+            ("none", vm) => Virtualization::Vm { technology: vm.to_string() },
+            (container, _) => Virtualization::Container { technology: container.to_string() },
+        };
+    }
+    if let Some(technology) = container.first() {
+        return Virtualization::Container { technology: technology.to_string() };
+    }
+    let dmi = dmi.join(" ");
+    let vm = [
+        ("QEMU", "qemu"),
+        ("KVM", "kvm"),
+        ("VMware", "vmware"),
+        ("VirtualBox", "oracle"),
+        ("innotek", "oracle"),
+        ("Xen", "xen"),
+        ("Microsoft Corporation Virtual Machine", "microsoft"),
+        ("Amazon EC2", "amazon"),
+    ]
+    .iter()
+    .find(|(hint, _)| dmi.contains(hint));
+    match vm {
+        Some((_, technology)) => Virtualization::Vm { technology: technology.to_string() },
+        None => Virtualization::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_debian_vm() {
+        let output = [
+            "--- os-release",
+            "PRETTY_NAME=\"Debian GNU/Linux 12 (bookworm)\"",
+            "NAME=\"Debian GNU/Linux\"",
+            "VERSION_ID=\"12\"",
+            "ID=debian",
+            "--- uname",
+            "Linux",
+            "6.1.0-18-amd64",
+            "x86_64",
+            "--- init",
+            "systemd",
+            "systemd",
+            "--- shell",
+            "/bin/bash",
+            "--- userland",
+            "ls (GNU coreutils) 9.1",
+            "/usr/bin/ls",
+            "--- sudo",
+            "/usr/bin/sudo",
+            "--- doas",
+            "--- virt",
+            "none",
+            "kvm",
+            "--- container",
+            "--- dmi",
+            "QEMU",
+            "Standard PC (Q35 + ICH9, 2009)",
+        ]
+        .join("\n");
+
+        let facts = HostFacts::parse(&output).unwrap();
+        let os = facts.os.as_ref().unwrap();
+        assert_eq!(os.id, "debian");
+        assert!(os.id_like.is_empty());
+        assert_eq!(os.pretty_name.as_deref(), Some("Debian GNU/Linux 12 (bookworm)"));
+        assert_eq!(os.version_id.as_deref(), Some("12"));
+        assert!(facts.is_linux());
+        assert_eq!(facts.arch, "x86_64");
+        assert_eq!(facts.init, InitSystem::Systemd);
+        assert_eq!(facts.shell.as_deref(), Some("/bin/bash"));
+        assert_eq!(facts.userland, Userland::Gnu);
+        assert!(facts.has_sudo && !facts.has_doas);
+        assert_eq!(facts.virtualization, Virtualization::Vm { technology: "kvm".to_string() });
+    }
+
+    #[test]
+    fn test_parse_alpine_container() {
+        let output = [
+            "--- os-release",
+            "NAME=\"Alpine Linux\"",
+            "ID=alpine",
+            "VERSION_ID=3.19.1",
+            "--- uname",
+            "Linux",
+            "6.1.0-18-amd64",
+            "aarch64",
+            "--- init",
+            "sh",
+            "--- shell",
+            "",
+            "--- userland",
+            "ls: unrecognized option: version",
+            "/bin/busybox",
+            "--- sudo",
+            "--- doas",
+            "/usr/bin/doas",
+            "--- virt",
+            "--- container",
+            "docker",
+            "--- dmi",
+        ]
+        .join("\n");
+
+        let facts = HostFacts::parse(&output).unwrap();
+        assert_eq!(facts.os.unwrap().version_id.as_deref(), Some("3.19.1"));
+        assert_eq!(facts.init, InitSystem::Unknown);
+        assert_eq!(facts.shell, None);
+        assert_eq!(facts.userland, Userland::Busybox);
+        assert!(!facts.has_sudo && facts.has_doas);
+        assert_eq!(facts.virtualization, Virtualization::Container { technology: "docker".to_string() });
+
+        assert!(HostFacts::parse("sh: not found").is_err());
+    }
+}
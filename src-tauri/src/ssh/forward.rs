@@ -0,0 +1,475 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, Weak,
+    },
+};
+
+use anyhow::{bail, Context, Result};
+use russh::{client, Channel};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::RwLock,
+    task::JoinHandle,
+};
+
+use super::{
+    client::Client,
+    session::{random_id, Session},
+};
+
+const BUFFER_SIZE: usize = 32 * 1024;
+
+const SOCKS_VERSION: u8 = 5;
+const SOCKS_NO_AUTHENTICATION: u8 = 0;
+const SOCKS_NO_ACCEPTABLE_METHODS: u8 = 0xff;
+const SOCKS_CONNECT: u8 = 1;
+const SOCKS_SUCCEEDED: u8 = 0;
+const SOCKS_HOST_UNREACHABLE: u8 = 4;
+const SOCKS_COMMAND_NOT_SUPPORTED: u8 = 7;
+const SOCKS_ADDRESS_TYPE_NOT_SUPPORTED: u8 = 8;
+
+/// What to forward, named after the matching `ssh` flags.
+/// A `bind_port` of 0 picks a free port, the one actually bound is reported back.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ForwardSpec {
+    /// `-L`: a local port, connections are made from the server to `remote_host`
+    #[serde(rename_all = "camelCase")]
+    Local { bind_address: String, bind_port: u16, remote_host: String, remote_port: u16 },
+    /// `-R`: a port on the server, connections are made from here to `local_host`
+    #[serde(rename_all = "camelCase")]
+    Remote { bind_address: String, bind_port: u16, local_host: String, local_port: u16 },
+    /// `-D`: a local SOCKS5 proxy, connections are made from the server.
+    /// It has no authentication, so it is only allowed to listen on loopback.
+    #[serde(rename_all = "camelCase")]
+    Dynamic { bind_address: String, bind_port: u16 },
+}
+
+impl ForwardSpec {
+    fn bind(&self) -> (&str, u16) {
+        match self {
+            ForwardSpec::Local { bind_address, bind_port, .. }
+            | ForwardSpec::Remote { bind_address, bind_port, .. }
+            | ForwardSpec::Dynamic { bind_address, bind_port } => (bind_address, *bind_port),
+        }
+    }
+
+    fn set_bind_port(&mut self, port: u16) {
+        match self {
+            ForwardSpec::Local { bind_port, .. }
+            | ForwardSpec::Remote { bind_port, .. }
+            | ForwardSpec::Dynamic { bind_port, .. } => *bind_port = port,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ForwardInfo {
+    id: usize,
+    #[serde(flatten)]
+    spec: ForwardSpec,
+    /// Bytes that went into the tunnel
+    bytes_sent: u64,
+    /// Bytes that came out of the tunnel
+    bytes_received: u64,
+    active_connections: usize,
+    /// Why a remote forward could not be restored after a reconnect
+    error: Option<String>,
+}
+
+#[derive(Default)]
+struct Counters {
+    sent: AtomicU64,
+    received: AtomicU64,
+    connections: AtomicUsize,
+}
+
+/// A running forward. Stopping it only stops new connections,
+/// the ones already tunneled are left to finish.
+pub struct Forward {
+    id: usize,
+    spec: ForwardSpec,
+    counters: Arc<Counters>,
+    /// Accept loop of the local listener, remote forwards are served by the connection handler
+    listener: Option<JoinHandle<()>>,
+    error: Option<String>,
+}
+
+impl Forward {
+    /// Binds the local end of a `-L` or `-D` forward. Connections go through
+    /// whatever connection `session` has at the time, so they survive reconnects.
+    pub async fn listen(mut spec: ForwardSpec, session: Weak<RwLock<Session>>) -> Result<Self> {
+        match &spec {
+            ForwardSpec::Remote { .. } => bail!("Remote forwards are bound on the server"),
+            ForwardSpec::Dynamic { bind_address, .. } if !is_loopback(bind_address) => {
+                bail!("A SOCKS proxy can't listen on {}, only on loopback", bind_address)
+            }
+            _ => {}
+        }
+        let (address, port) = spec.bind();
+        let listener = TcpListener::bind((address, port))
+            .await
+            .with_context(|| format!("Failed to listen on {}:{}", address, port))?;
+        spec.set_bind_port(listener.local_addr()?.port());
+
+        let counters = Arc::<Counters>::default();
+        let task = tokio::spawn(accept_loop(listener, spec.clone(), session, counters.clone()));
+        Ok(Self { id: random_id(), spec, counters, listener: Some(task), error: None })
+    }
+
+    /// Asks the server to listen for a `-R` forward
+    pub async fn request(
+        mut spec: ForwardSpec,
+        handle: &mut client::Handle<Client>,
+        remote_forwards: &RemoteForwards,
+    ) -> Result<Self> {
+        let ForwardSpec::Remote { local_host, local_port, .. } = &spec else {
+            bail!("Only remote forwards are bound on the server");
+        };
+        let target = (local_host.clone(), *local_port);
+        let (address, port) = spec.bind();
+        let bound = match handle.tcpip_forward(address, port as u32).await {
+            // The server only says which port it picked when asked to pick one
+            Ok(0) => port,
+            Ok(bound) => bound as u16,
+            Err(e) => {
+                return Err(e).with_context(|| format!("The server refused to listen on {}:{}", address, port))
+            }
+        };
+        spec.set_bind_port(bound);
+
+        let counters = Arc::<Counters>::default();
+        remote_forwards.add(spec.bind(), target, counters.clone());
+        Ok(Self { id: random_id(), spec, counters, listener: None, error: None })
+    }
+
+    /// Remote forwards live on the server, a new connection has to ask for them again
+    pub async fn restore(&mut self, handle: &mut client::Handle<Client>) {
+        if let ForwardSpec::Remote { .. } = self.spec {
+            let (address, port) = self.spec.bind();
+            self.error = handle
+                .tcpip_forward(address, port as u32)
+                .await
+                .err()
+                .map(|e| format!("The server refused to listen on {}:{}: {}", address, port, e));
+        }
+    }
+
+    /// Tells the server to stop listening for remote forwards
+    pub async fn cancel(self, handle: &client::Handle<Client>, remote_forwards: &RemoteForwards) -> Result<()> {
+        if let ForwardSpec::Remote { .. } = self.spec {
+            let (address, port) = self.spec.bind();
+            remote_forwards.remove(self.spec.bind());
+            if !handle.is_closed() {
+                handle.cancel_tcpip_forward(address, port as u32).await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn info(&self) -> ForwardInfo {
+        ForwardInfo {
+            id: self.id,
+            spec: self.spec.clone(),
+            bytes_sent: self.counters.sent.load(Ordering::Relaxed),
+            bytes_received: self.counters.received.load(Ordering::Relaxed),
+            active_connections: self.counters.connections.load(Ordering::Relaxed),
+            error: self.error.clone(),
+        }
+    }
+}
+
+impl Drop for Forward {
+    fn drop(&mut self) {
+        if let Some(listener) = &self.listener {
+            listener.abort();
+        }
+    }
+}
+
+struct RemoteTarget {
+    host: String,
+    port: u16,
+    counters: Arc<Counters>,
+}
+
+/// Where the connections the server forwards to us should go.
+/// Shared with the connection handler, which is the one receiving them.
+#[derive(Clone, Default)]
+pub struct RemoteForwards {
+    targets: Arc<Mutex<HashMap<(String, u16), RemoteTarget>>>,
+}
+
+impl RemoteForwards {
+    fn add(&self, (address, port): (&str, u16), (host, target_port): (String, u16), counters: Arc<Counters>) {
+        let target = RemoteTarget { host, port: target_port, counters };
+        self.targets.lock().unwrap().insert((address.to_string(), port), target);
+    }
+
+    fn remove(&self, (address, port): (&str, u16)) {
+        self.targets.lock().unwrap().remove(&(address.to_string(), port));
+    }
+
+    pub fn clear(&self) {
+        self.targets.lock().unwrap().clear();
+    }
+
+    /// Connects a channel the server opened to the local end of its forward
+    pub fn accept(&self, channel: Channel<client::Msg>, address: &str, port: u32) {
+        let target = self
+            .targets
+            .lock()
+            .unwrap()
+            .get(&(address.to_string(), port as u16))
+            .map(|target| (target.host.clone(), target.port, target.counters.clone()));
+        tokio::spawn(async move {
+            let Some((host, port, counters)) = target else {
+                let _ = channel.close().await;
+                return;
+            };
+            match TcpStream::connect((host.as_str(), port)).await {
+                Ok(stream) => {
+                    let _ = bridge(stream, channel.into_stream(), &counters).await;
+                }
+                Err(_) => {
+                    let _ = channel.close().await;
+                }
+            }
+        });
+    }
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    spec: ForwardSpec,
+    session: Weak<RwLock<Session>>,
+    counters: Arc<Counters>,
+) {
+    while let Ok((stream, originator)) = listener.accept().await {
+        let (spec, session, counters) = (spec.clone(), session.clone(), counters.clone());
+        tokio::spawn(async move {
+            let _ = serve(stream, originator, &spec, &session, &counters).await;
+        });
+    }
+}
+
+async fn serve(
+    mut stream: TcpStream,
+    originator: SocketAddr,
+    spec: &ForwardSpec,
+    session: &Weak<RwLock<Session>>,
+    counters: &Counters,
+) -> Result<()> {
+    let channel = match spec {
+        ForwardSpec::Local { remote_host, remote_port, .. } => {
+            open_direct(session, remote_host, *remote_port, originator).await?
+        }
+        ForwardSpec::Dynamic { .. } => {
+            let (host, port) = socks5_handshake(&mut stream).await?;
+            match open_direct(session, &host, port, originator).await {
+                Ok(channel) => {
+                    socks5_reply(&mut stream, SOCKS_SUCCEEDED).await?;
+                    channel
+                }
+                Err(e) => {
+                    socks5_reply(&mut stream, SOCKS_HOST_UNREACHABLE).await?;
+                    return Err(e);
+                }
+            }
+        }
+        ForwardSpec::Remote { .. } => return Ok(()),
+    };
+    bridge(stream, channel.into_stream(), counters).await
+}
+
+async fn open_direct(
+    session: &Weak<RwLock<Session>>,
+    host: &str,
+    port: u16,
+    originator: SocketAddr,
+) -> Result<Channel<client::Msg>> {
+    let handle = session.upgrade().context("The session is gone")?.read().await.handle();
+    let handle = handle.read().await;
+    handle
+        .channel_open_direct_tcpip(host, port as u32, &originator.ip().to_string(), originator.port() as u32)
+        .await
+        .with_context(|| format!("{}:{} is not reachable from the server", host, port))
+}
+
+fn is_loopback(address: &str) -> bool {
+    address == "localhost" || address.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Copies both ways until both sides are done, `local` is the end outside the tunnel
+async fn bridge<L, T>(local: L, tunnel: T, counters: &Counters) -> Result<()>
+where
+    L: AsyncRead + AsyncWrite,
+    T: AsyncRead + AsyncWrite,
+{
+    let (mut local_read, mut local_write) = tokio::io::split(local);
+    let (mut tunnel_read, mut tunnel_write) = tokio::io::split(tunnel);
+    counters.connections.fetch_add(1, Ordering::Relaxed);
+    let result = tokio::try_join!(
+        pipe(&mut local_read, &mut tunnel_write, &counters.sent),
+        pipe(&mut tunnel_read, &mut local_write, &counters.received),
+    );
+    counters.connections.fetch_sub(1, Ordering::Relaxed);
+    result.map(|_| ())
+}
+
+async fn pipe<R, W>(reader: &mut R, writer: &mut W, counter: &AtomicU64) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buffer = vec![0; BUFFER_SIZE];
+    loop {
+        let read = reader.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        writer.write_all(&buffer[..read]).await?;
+        counter.fetch_add(read as u64, Ordering::Relaxed);
+    }
+    writer.shutdown().await?;
+    Ok(())
+}
+
+/// Reads the greeting and CONNECT request of a SOCKS5 client, returning where it wants to go.
+/// Only "no authentication" is offered, the proxy only listens on loopback.
+async fn socks5_handshake<S>(stream: &mut S) -> Result<(String, u16)>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut greeting = [0; 2];
+    stream.read_exact(&mut greeting).await?;
+    if greeting[0] != SOCKS_VERSION {
+        bail!("Not a SOCKS5 client");
+    }
+    let mut methods = vec![0; greeting[1] as usize];
+    stream.read_exact(&mut methods).await?;
+    if !methods.contains(&SOCKS_NO_AUTHENTICATION) {
+        stream.write_all(&[SOCKS_VERSION, SOCKS_NO_ACCEPTABLE_METHODS]).await?;
+        bail!("The SOCKS client insists on authenticating");
+    }
+    stream.write_all(&[SOCKS_VERSION, SOCKS_NO_AUTHENTICATION]).await?;
+
+    let mut request = [0; 4];
+    stream.read_exact(&mut request).await?;
+    let [_, command, _, address_type] = request;
+    let host = match address_type {
+        1 => {
+            let mut ip = [0; 4];
+            stream.read_exact(&mut ip).await?;
+            Ipv4Addr::from(ip).to_string()
+        }
+        3 => {
+            let mut name = vec![0; stream.read_u8().await? as usize];
+            stream.read_exact(&mut name).await?;
+            String::from_utf8(name).context("Invalid SOCKS host name")?
+        }
+        4 => {
+            let mut ip = [0; 16];
+            stream.read_exact(&mut ip).await?;
+            Ipv6Addr::from(ip).to_string()
+        }
+        _ => {
+            socks5_reply(stream, SOCKS_ADDRESS_TYPE_NOT_SUPPORTED).await?;
+            bail!("Unknown SOCKS address type {}", address_type);
+        }
+    };
+    let port = stream.read_u16().await?;
+    if command != SOCKS_CONNECT {
+        socks5_reply(stream, SOCKS_COMMAND_NOT_SUPPORTED).await?;
+        bail!("Only SOCKS CONNECT is supported");
+    }
+    Ok((host, port))
+}
+
+/// The bound address means nothing through a tunnel, it is always sent as 0.0.0.0:0
+async fn socks5_reply<S>(stream: &mut S, status: u8) -> Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    stream.write_all(&[SOCKS_VERSION, status, 0, 1, 0, 0, 0, 0, 0, 0]).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_socks5_handshake() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        client.write_all(&[5, 2, 2, 0]).await.unwrap();
+        client.write_all(&[5, 1, 0, 3, 11]).await.unwrap();
+        client.write_all(b"grafana.lan").await.unwrap();
+        client.write_all(&3000u16.to_be_bytes()).await.unwrap();
+
+        let destination = socks5_handshake(&mut server).await.unwrap();
+        assert_eq!(destination, ("grafana.lan".to_string(), 3000));
+        let mut method = [0; 2];
+        client.read_exact(&mut method).await.unwrap();
+        assert_eq!(method, [5, 0]);
+
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        client.write_all(&[5, 1, 0, 5, 2, 0, 1, 10, 0, 0, 1, 0, 22]).await.unwrap();
+        assert!(socks5_handshake(&mut server).await.is_err());
+        let mut replies = [0; 12];
+        client.read_exact(&mut replies).await.unwrap();
+        assert_eq!(replies[2..4], [5, SOCKS_COMMAND_NOT_SUPPORTED]);
+    }
+
+    #[tokio::test]
+    async fn test_dynamic_forward_loopback_only() {
+        let dynamic = |bind_address: &str| ForwardSpec::Dynamic {
+            bind_address: bind_address.to_string(),
+            bind_port: 0,
+        };
+        for exposed in ["0.0.0.0", "::", "192.168.0.3"] {
+            assert!(Forward::listen(dynamic(exposed), Weak::new()).await.is_err());
+        }
+        let forward = Forward::listen(dynamic("127.0.0.1"), Weak::new()).await.unwrap();
+        assert_ne!(forward.spec.bind().1, 0);
+        assert!(is_loopback("localhost") && is_loopback("::1"));
+    }
+
+    #[tokio::test]
+    async fn test_bridge_counters() {
+        let (mut local, local_end) = tokio::io::duplex(1024);
+        let (mut tunnel, tunnel_end) = tokio::io::duplex(1024);
+        let counters = Counters::default();
+
+        let peers = async {
+            local.write_all(b"GET / HTTP/1.0\r\n\r\n").await.unwrap();
+            local.shutdown().await.unwrap();
+            tunnel.write_all(b"HTTP/1.0 200 OK\r\n").await.unwrap();
+            tunnel.shutdown().await.unwrap();
+
+            let mut request = Vec::new();
+            tunnel.read_to_end(&mut request).await.unwrap();
+            let mut response = Vec::new();
+            local.read_to_end(&mut response).await.unwrap();
+            (request, response)
+        };
+        let (bridged, (request, response)) = tokio::join!(bridge(local_end, tunnel_end, &counters), peers);
+
+        bridged.unwrap();
+        assert_eq!(request, b"GET / HTTP/1.0\r\n\r\n");
+        assert_eq!(response, b"HTTP/1.0 200 OK\r\n");
+        assert_eq!(counters.sent.load(Ordering::Relaxed), 18);
+        assert_eq!(counters.received.load(Ordering::Relaxed), 17);
+        assert_eq!(counters.connections.load(Ordering::Relaxed), 0);
+    }
+}
@@ -1,24 +1,309 @@
-use std::net::{IpAddr, Ipv4Addr};
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
 use anyhow::Result;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tauri::regex::Regex;
 
-use super::command::{CommandString, ConcreteCommand, VirtualCommand};
+use super::{
+    command::{CommandOutput, ConcreteCommand, VirtualCommand},
+    facts::HostFacts,
+    shell::ShellCommand,
+};
 
 #[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct Interface {
     name: String,
-    mac: MAC,
-    cidr: CIDR,
-    status: String,
+    /// Missing on layer 3 links like wireguard or tun
+    mac: Option<MAC>,
+    addresses: Vec<Address>,
+    status: OperState,
+    mtu: Option<u32>,
+    flags: Vec<LinkFlag>,
+    link_type: LinkType,
+    /// Mbit/s, only known for physical links with a carrier
+    speed: Option<u32>,
+    duplex: Option<Duplex>,
+    /// The bridge or bond this link is a port of
+    master: Option<String>,
+    /// The link this one sits on, like `eth0` for a VLAN. `if12` when it's in another namespace,
+    /// as for the far end of a veth.
+    parent: Option<String>,
 }
 
 impl Interface {
-    pub fn new(name: String, mac: MAC, cidr: CIDR, status: String) -> Self {
-        Self { name, mac, cidr, status }
+    pub fn new(name: String, mac: Option<MAC>, addresses: Vec<Address>, status: OperState) -> Self {
+        Self {
+            name,
+            mac,
+            addresses,
+            status,
+            mtu: None,
+            flags: Vec::new(),
+            link_type: LinkType::Other,
+            speed: None,
+            duplex: None,
+            master: None,
+            parent: None,
+        }
+    }
+
+    fn set_speed(&mut self, attributes: &HashMap<&str, &str>) {
+        // Links without a carrier fail to read, some drivers say -1 or u32::MAX
+        self.speed = attributes.get("speed")
+            .and_then(|speed| speed.parse().ok())
+            .filter(|speed| *speed != u32::MAX);
+        self.duplex = attributes.get("duplex").and_then(|duplex| Duplex::parse(duplex));
+    }
+}
+
+/// RFC 2863 operational state, as the kernel reports it
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum OperState {
+    Up,
+    Down,
+    /// What virtual links without a carrier, like loopback or tun, usually say
+    Unknown,
+    Dormant,
+    LowerLayerDown,
+    NotPresent,
+    Testing,
+}
+
+impl OperState {
+    /// `ip` says `UP` and sysfs `up`
+    fn parse(value: &str) -> Self {
+        match value.to_lowercase().as_str() {
+            "up" => OperState::Up,
+            "down" => OperState::Down,
+            "dormant" => OperState::Dormant,
+            "lowerlayerdown" => OperState::LowerLayerDown,
+            "notpresent" => OperState::NotPresent,
+            "testing" => OperState::Testing,
+            _ => OperState::Unknown,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum LinkFlag {
+    Up,
+    Broadcast,
+    Loopback,
+    PointToPoint,
+    Running,
+    NoArp,
+    Promisc,
+    AllMulti,
+    Master,
+    Slave,
+    Multicast,
+    Dynamic,
+    LowerUp,
+    Dormant,
+    NoCarrier,
+}
+
+impl LinkFlag {
+    /// The `IFF_` bits of `/sys/class/net/*/flags`, `LOWER_UP` and `DORMANT` aren't in there
+    const BITS: [(u32, LinkFlag); 12] = [
+        (0x1, LinkFlag::Up),
+        (0x2, LinkFlag::Broadcast),
+        (0x8, LinkFlag::Loopback),
+        (0x10, LinkFlag::PointToPoint),
+        (0x40, LinkFlag::Running),
+        (0x80, LinkFlag::NoArp),
+        (0x100, LinkFlag::Promisc),
+        (0x200, LinkFlag::AllMulti),
+        (0x400, LinkFlag::Master),
+        (0x800, LinkFlag::Slave),
+        (0x1000, LinkFlag::Multicast),
+        (0x8000, LinkFlag::Dynamic),
+    ];
+
+    /// The names `ip` and `ifconfig` use, like `LOWER_UP`
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "UP" => Some(LinkFlag::Up),
+            "BROADCAST" => Some(LinkFlag::Broadcast),
+            "LOOPBACK" => Some(LinkFlag::Loopback),
+            "POINTOPOINT" => Some(LinkFlag::PointToPoint),
+            "RUNNING" => Some(LinkFlag::Running),
+            "NOARP" => Some(LinkFlag::NoArp),
+            "PROMISC" => Some(LinkFlag::Promisc),
+            "ALLMULTI" => Some(LinkFlag::AllMulti),
+            "MASTER" => Some(LinkFlag::Master),
+            "SLAVE" => Some(LinkFlag::Slave),
+            "MULTICAST" => Some(LinkFlag::Multicast),
+            "DYNAMIC" => Some(LinkFlag::Dynamic),
+            "LOWER_UP" => Some(LinkFlag::LowerUp),
+            "DORMANT" => Some(LinkFlag::Dormant),
+            "NO-CARRIER" => Some(LinkFlag::NoCarrier),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum LinkType {
+    Ether,
+    Loopback,
+    Bridge,
+    Vlan,
+    Bond,
+    Wireguard,
+    Veth,
+    Tun,
+    Other,
+}
+
+impl LinkType {
+    /// Link kinds as `ip` and the kernel name them
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "ether" => Some(LinkType::Ether),
+            "loopback" => Some(LinkType::Loopback),
+            "bridge" => Some(LinkType::Bridge),
+            "vlan" => Some(LinkType::Vlan),
+            "bond" => Some(LinkType::Bond),
+            "wireguard" => Some(LinkType::Wireguard),
+            "veth" => Some(LinkType::Veth),
+            "tun" => Some(LinkType::Tun),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum Duplex {
+    Full,
+    Half,
+}
+
+impl Duplex {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "full" => Some(Duplex::Full),
+            "half" => Some(Duplex::Half),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Address {
+    family: AddressFamily,
+    cidr: CIDR,
+    /// IPv4 only, and not on every interface
+    broadcast: Option<Ipv4Addr>,
+    scope: AddressScope,
+    /// Only known from `ip`
+    lifetime: Option<Lifetime>,
+    flags: Vec<AddressFlag>,
+}
+
+impl Address {
+    fn new(cidr: CIDR) -> Self {
+        Self {
+            family: match cidr.0 {
+                IpAddr::V4(_) => AddressFamily::Ipv4,
+                IpAddr::V6(_) => AddressFamily::Ipv6,
+            },
+            scope: AddressScope::of(&cidr.0),
+            cidr,
+            broadcast: None,
+            lifetime: None,
+            flags: Vec::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum AddressFamily {
+    Ipv4,
+    Ipv6,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum AddressScope {
+    Global,
+    Site,
+    Link,
+    Host,
+}
+
+impl AddressScope {
+    /// For when the tool doesn't say, the address range does
+    fn of(ip: &IpAddr) -> Self {
+        match ip {
+            IpAddr::V4(ip) if ip.is_loopback() => AddressScope::Host,
+            IpAddr::V4(ip) if ip.is_link_local() => AddressScope::Link,
+            IpAddr::V6(ip) if ip.is_loopback() => AddressScope::Host,
+            IpAddr::V6(ip) if ip.segments()[0] & 0xffc0 == 0xfe80 => AddressScope::Link,
+            IpAddr::V6(ip) if ip.segments()[0] & 0xffc0 == 0xfec0 => AddressScope::Site,
+            _ => AddressScope::Global,
+        }
+    }
+
+    /// `ip` says `link`, busybox `Link`, net-tools `0x20<link>`
+    fn parse(value: &str) -> Option<Self> {
+        let value = value.split_once('<').map_or(value, |(_, name)| name.trim_end_matches('>'));
+        match value.to_lowercase().as_str() {
+            "global" | "universe" => Some(AddressScope::Global),
+            "site" => Some(AddressScope::Site),
+            "link" => Some(AddressScope::Link),
+            "host" => Some(AddressScope::Host),
+            _ => None,
+        }
     }
 }
 
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum AddressFlag {
+    /// Handed out by DHCP or SLAAC, as opposed to configured
+    Dynamic,
+    Secondary,
+    Temporary,
+    Deprecated,
+    Tentative,
+    NoPrefixRoute,
+    ManageTempAddr,
+}
+
+impl AddressFlag {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "dynamic" => Some(AddressFlag::Dynamic),
+            "secondary" => Some(AddressFlag::Secondary),
+            "temporary" => Some(AddressFlag::Temporary),
+            "deprecated" => Some(AddressFlag::Deprecated),
+            "tentative" => Some(AddressFlag::Tentative),
+            "noprefixroute" => Some(AddressFlag::NoPrefixRoute),
+            "mngtmpaddr" => Some(AddressFlag::ManageTempAddr),
+            _ => None,
+        }
+    }
+}
+
+/// Seconds left, `None` meaning forever
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Lifetime {
+    valid: Option<u32>,
+    preferred: Option<u32>,
+}
+
 #[derive(Serialize, Debug, PartialEq)]
 struct MAC([u8; 6]);
 
@@ -61,6 +346,12 @@ impl CIDR {
                 .unwrap() as u8,
         )
     }
+
+    /// The address alone, as a /32 or /128
+    fn host(ip: IpAddr) -> Self {
+        let prefix = if ip.is_ipv4() { 32 } else { 128 };
+        Self(ip, prefix)
+    }
 }
 
 impl std::fmt::Display for CIDR {
@@ -86,87 +377,307 @@ impl TryFrom<&str> for CIDR {
 pub struct ListInterfaces;
 
 impl ListInterfaces {
-    const IMPLEMENTATIONS: [&'static dyn ConcreteCommand<Vec<Interface>>; 2] =
-        [&ListInterfacesIp {}, &ListInterfacesIfConfig {}];
+    const IMPLEMENTATIONS: [&'static dyn ConcreteCommand<Vec<Interface>>; 4] = [
+        &ListInterfacesIpJson {},
+        &ListInterfacesIp {},
+        &ListInterfacesIfConfig {},
+        &ListInterfacesSysfs {},
+    ];
 }
 
-impl VirtualCommand<Vec<Interface>, 2> for ListInterfaces {
-    fn implementations(&self) -> [&'static dyn ConcreteCommand<Vec<Interface>>; 2] {
+impl VirtualCommand<Vec<Interface>, 4> for ListInterfaces {
+    fn implementations(&self) -> [&'static dyn ConcreteCommand<Vec<Interface>>; 4] {
         Self::IMPLEMENTATIONS
     }
 }
 
+/// `ip` doesn't know the link speeds, they're read from sysfs after the JSON
+const IP_JSON_SCRIPT: &str = r#"
+ip -j -d addr || exit
+echo "--- speed"
+for dev in /sys/class/net/*; do
+    [ -d "$dev" ] || continue
+    echo "${dev##*/} speed=$(cat "$dev/speed" 2>/dev/null) duplex=$(cat "$dev/duplex" 2>/dev/null)"
+done"#;
+
+/// `ip -j`, iproute2 has had JSON output since 4.14
+struct ListInterfacesIpJson;
+
+impl ConcreteCommand<Vec<Interface>> for ListInterfacesIpJson {
+    fn detection_command(&self) -> ShellCommand {
+        ShellCommand::new("ip").args(["-j", "-d", "addr", "show", "lo"])
+    }
+
+    fn execution_command(&self) -> ShellCommand {
+        ShellCommand::script(IP_JSON_SCRIPT)
+    }
+
+    /// Older versions reject `-j`, some ignore it and print text
+    fn parse_detection_output(&self, output: &CommandOutput) -> Result<bool> {
+        Ok(output.success() && output.stdout.trim_start().starts_with('['))
+    }
+
+    fn supports(&self, facts: &HostFacts) -> Option<bool> {
+        (!facts.is_linux()).then_some(false)
+    }
+
+    fn parse_execution_output(&self, output: &str) -> Result<Vec<Interface>> {
+        let (json, speeds) = output.split_once("--- speed").unwrap_or((output, ""));
+        let links: Vec<IpLink> = serde_json::from_str(json)
+            .map_err(|e| anyhow::anyhow!("Invalid ip JSON output: {}", e))?;
+        let speeds: HashMap<_, HashMap<_, _>> = speeds.lines()
+            .filter_map(|line| {
+                let mut words = line.split_whitespace();
+                Some((words.next()?, words.filter_map(|word| word.split_once('=')).collect()))
+            })
+            .collect();
+
+        Ok(links.into_iter().map(|link| {
+            let mut interface = Interface::from(link);
+            if let Some(attributes) = speeds.get(interface.name.as_str()) {
+                interface.set_speed(attributes);
+            }
+            interface
+        }).collect())
+    }
+}
+
+/// One entry of `ip -j -d addr`, only what `Interface` needs
+#[derive(Deserialize)]
+struct IpLink {
+    ifname: String,
+    operstate: String,
+    /// Not always a MAC, tunnels put their IPv4 endpoint here
+    address: Option<String>,
+    #[serde(default)]
+    flags: Vec<String>,
+    mtu: Option<u32>,
+    link_type: Option<String>,
+    master: Option<String>,
+    /// The parent, when it's in the same namespace
+    link: Option<String>,
+    link_index: Option<u32>,
+    linkinfo: Option<IpLinkInfo>,
+    #[serde(default)]
+    addr_info: Vec<IpAddrInfo>,
+}
+
+#[derive(Deserialize)]
+struct IpLinkInfo {
+    info_kind: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct IpAddrInfo {
+    local: Option<IpAddr>,
+    prefixlen: Option<u8>,
+    broadcast: Option<Ipv4Addr>,
+    scope: Option<String>,
+    valid_life_time: Option<u32>,
+    preferred_life_time: Option<u32>,
+    #[serde(default)]
+    dynamic: bool,
+    #[serde(default)]
+    secondary: bool,
+    #[serde(default)]
+    temporary: bool,
+    #[serde(default)]
+    deprecated: bool,
+    #[serde(default)]
+    tentative: bool,
+    #[serde(default)]
+    noprefixroute: bool,
+    #[serde(default)]
+    mngtmpaddr: bool,
+}
+
+impl From<IpLink> for Interface {
+    fn from(link: IpLink) -> Self {
+        let mac = link.address.and_then(|address| MAC::try_from(address.as_str()).ok());
+        let addresses = link.addr_info.into_iter().filter_map(Address::from_ip_json).collect();
+        let mut interface = Interface::new(link.ifname, mac, addresses, OperState::parse(&link.operstate));
+        interface.mtu = link.mtu;
+        interface.flags = link.flags.iter().filter_map(|flag| LinkFlag::parse(flag)).collect();
+        // The kind is more telling, a bridge's link type is `ether` too
+        interface.link_type = link.linkinfo.and_then(|info| info.info_kind)
+            .into_iter()
+            .chain(link.link_type)
+            .find_map(|kind| LinkType::parse(&kind))
+            .unwrap_or(LinkType::Other);
+        interface.master = link.master;
+        interface.parent = link.link.or(link.link_index.map(|index| format!("if{}", index)));
+        interface
+    }
+}
+
+impl Address {
+    /// `None` for the empty entries `ip` leaves for addresses it can't show
+    fn from_ip_json(info: IpAddrInfo) -> Option<Self> {
+        let ip = info.local?;
+        let cidr = match info.prefixlen {
+            Some(prefix) => CIDR(ip, prefix),
+            None => CIDR::host(ip),
+        };
+
+        let mut address = Address::new(cidr);
+        address.broadcast = info.broadcast;
+        if let Some(scope) = info.scope.as_deref().and_then(AddressScope::parse) {
+            address.scope = scope;
+        }
+        // u32::MAX is how the kernel says forever
+        let seconds = |value: u32| (value != u32::MAX).then_some(value);
+        address.lifetime = info.valid_life_time.map(|valid| Lifetime {
+            valid: seconds(valid),
+            preferred: info.preferred_life_time.and_then(seconds),
+        });
+        address.flags = [
+            (info.secondary, AddressFlag::Secondary),
+            (info.temporary, AddressFlag::Temporary),
+            (info.dynamic, AddressFlag::Dynamic),
+            (info.deprecated, AddressFlag::Deprecated),
+            (info.tentative, AddressFlag::Tentative),
+            (info.noprefixroute, AddressFlag::NoPrefixRoute),
+            (info.mngtmpaddr, AddressFlag::ManageTempAddr),
+        ]
+        .into_iter()
+        .filter_map(|(set, flag)| set.then_some(flag))
+        .collect();
+        Some(address)
+    }
+}
+
 struct ListInterfacesIp;
 
 impl ConcreteCommand<Vec<Interface>> for ListInterfacesIp {
-    fn detection_command(&self) -> CommandString {
-        CommandString::Static("ip -V")
+    fn detection_command(&self) -> ShellCommand {
+        ShellCommand::new("ip").arg("-V")
+    }
+
+    fn execution_command(&self) -> ShellCommand {
+        ShellCommand::new("ip").arg("a")
     }
 
-    fn execution_command(&self) -> CommandString {
-        CommandString::Static("ip a")
+    fn parse_detection_output(&self, output: &CommandOutput) -> Result<bool> {
+        Ok(output.success() && output.stdout.contains("ip utility"))
     }
 
-    fn parse_detection_output(&self, output: &str) -> Result<bool> {
-        Ok(output.contains("ip utility"))
+    /// iproute2 only exists on Linux
+    fn supports(&self, facts: &HostFacts) -> Option<bool> {
+        (!facts.is_linux()).then_some(false)
     }
 
     fn parse_execution_output(&self, output: &str) -> Result<Vec<Interface>> {
-        let name_re = Regex::new(r"^(\w+)[:@]").unwrap();
+        // Like `eth0.10@eth0: <BROADCAST,MULTICAST,UP,LOWER_UP> mtu 1500`
+        let header_re = Regex::new(r"^(\S+?)(?:@(\S+))?: <([^>]*)>").unwrap();
         let state_re = Regex::new(r"state\s(\w+)").unwrap();
-        let mac_re = Regex::new(r"(?m)^\s+\w+/\w+\s([\w:]+)").unwrap();
-        let cidr_re = Regex::new(r"inet\s([\d.]+/\d+)|inet6\s([\w:]+/\d+)").unwrap();
+        let mtu_re = Regex::new(r"\smtu\s(\d+)").unwrap();
+        let master_re = Regex::new(r"\smaster\s(\S+)").unwrap();
+        let link_type_re = Regex::new(r"(?m)^\s+link/(\w+)").unwrap();
+        let mac_re = Regex::new(r"(?m)^\s+link/\w+\s((?:[[:xdigit:]]{2}:){5}[[:xdigit:]]{2})\b").unwrap();
         let split_re = Regex::new(r"(?m)^\d+: ").unwrap();
 
         split_re.split(output).filter(|&s| !s.is_empty()).map(|lines| {
-            let name = name_re.captures(lines)
-                .and_then(|cap| cap.get(1))
-                .ok_or(anyhow::anyhow!("No valid interface name in '{}'", lines))?
-                .as_str().to_string();
+            let header = header_re.captures(lines)
+                .ok_or(anyhow::anyhow!("No valid interface name in '{}'", lines))?;
+            let name = header[1].to_string();
 
             let state = state_re.captures(lines)
                 .and_then(|cap| cap.get(1))
                 .ok_or(anyhow::anyhow!("No valid interface state in '{}'", lines))?
-                .as_str().to_string();
+                .as_str();
 
             let mac = mac_re.captures(lines)
                 .and_then(|cap| cap.get(1))
-                .ok_or(anyhow::anyhow!("No valid MAC address in '{}'", lines))?
-                .as_str().try_into()
+                .map(|mac| MAC::try_from(mac.as_str()))
+                .transpose()
                 .map_err(|e| anyhow::anyhow!("Invalid MAC address: {}", e))?;
 
-            let cidr = cidr_re.captures(lines)
-                .and_then(|cap| cap.get(1).or(cap.get(2)))
-                .ok_or(anyhow::anyhow!("No valid CIDR in '{}'", lines))?
-                .as_str().try_into()
-                .map_err(|e| anyhow::anyhow!("Invalid CIDR: {}", e))?;
+            let mut addresses: Vec<Address> = Vec::new();
+            for line in lines.lines().map(str::trim) {
+                if line.starts_with("inet ") || line.starts_with("inet6 ") {
+                    addresses.push(parse_ip_address(line)?);
+                } else if line.starts_with("valid_lft ") {
+                    if let Some(address) = addresses.last_mut() {
+                        address.lifetime = parse_lifetime(line);
+                    }
+                }
+            }
 
-            Ok(Interface::new(name, mac, cidr, state))
+            let mut interface = Interface::new(name, mac, addresses, OperState::parse(state));
+            interface.flags = header[3].split(',').filter_map(LinkFlag::parse).collect();
+            interface.parent = header.get(2).map(|parent| parent.as_str().to_string());
+            let capture = |re: &Regex| re.captures(lines).and_then(|cap| cap.get(1)).map(|value| value.as_str());
+            interface.mtu = capture(&mtu_re).and_then(|mtu| mtu.parse().ok());
+            interface.master = capture(&master_re).map(str::to_string);
+            interface.link_type = capture(&link_type_re).and_then(LinkType::parse).unwrap_or(LinkType::Other);
+            Ok(interface)
         }).collect::<Result<Vec<Interface>>>()
     }
 }
 
+/// Like `inet 192.168.0.3/24 brd 192.168.0.255 scope global dynamic eth0`
+fn parse_ip_address(line: &str) -> Result<Address> {
+    let mut words = line.split_whitespace().skip(1);
+    let cidr = words.next().ok_or(anyhow::anyhow!("No address in '{}'", line))?;
+    let cidr = match cidr.parse() {
+        // Point-to-point addresses come without a prefix, it's on the peer
+        Ok(ip) => CIDR::host(ip),
+        Err(_) => cidr.try_into().map_err(|e| anyhow::anyhow!("Invalid CIDR: {}", e))?,
+    };
+
+    let mut address = Address::new(cidr);
+    while let Some(word) = words.next() {
+        match word {
+            "brd" => address.broadcast = words.next().and_then(|brd| brd.parse().ok()),
+            "scope" => {
+                if let Some(scope) = words.next().and_then(AddressScope::parse) {
+                    address.scope = scope;
+                }
+            }
+            "peer" | "metric" | "proto" => {
+                words.next();
+            }
+            flag => address.flags.extend(AddressFlag::parse(flag)),
+        }
+    }
+    Ok(address)
+}
+
+/// Like `valid_lft forever preferred_lft 3600sec`
+fn parse_lifetime(line: &str) -> Option<Lifetime> {
+    let seconds = |value: &str| match value {
+        "forever" => Some(None),
+        value => value.strip_suffix("sec")?.parse().ok().map(Some),
+    };
+    match line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["valid_lft", valid, "preferred_lft", preferred, ..] => Some(Lifetime {
+            valid: seconds(valid)?,
+            preferred: seconds(preferred)?,
+        }),
+        _ => None,
+    }
+}
+
 struct ListInterfacesIfConfig;
 
 impl ConcreteCommand<Vec<Interface>> for ListInterfacesIfConfig {
-    fn detection_command(&self) -> CommandString {
-        CommandString::Static("ifconfig -s lo")
+    fn detection_command(&self) -> ShellCommand {
+        ShellCommand::new("ifconfig").args(["-s", "lo"])
     }
 
-    fn execution_command(&self) -> CommandString {
-        CommandString::Static("ifconfig")
+    fn execution_command(&self) -> ShellCommand {
+        ShellCommand::new("ifconfig")
     }
 
-    fn parse_detection_output(&self, output: &str) -> Result<bool> {
-        Ok(output.contains("Iface"))
+    fn parse_detection_output(&self, output: &CommandOutput) -> Result<bool> {
+        Ok(output.success() && output.stdout.contains("Iface"))
     }
 
     fn parse_execution_output(&self, output: &str) -> Result<Vec<Interface>> {
         let name_re = Regex::new(r"^[\w-]+").unwrap();
-        let status_re = Regex::new(r"<(\w+),").unwrap();
+        let flags_re = Regex::new(r"<([^>]*)>").unwrap();
+        let mtu_re = Regex::new(r"(?i)\bmtu[:\s](\d+)").unwrap();
         let mac_re = Regex::new(r"\s((?:\w\w:){5}\w\w)").unwrap();
-        let ip_re = Regex::new(r"inet\s([\d.]+)|inet6\s([\w:]+)").unwrap();
-        let mask_re = Regex::new(r"netmask\s([\d.]+)").unwrap();
 
         output.split("\n\n").filter(|&s| !s.is_empty()).map(|lines| {
             let name = name_re.captures(lines)
@@ -174,61 +685,365 @@ impl ConcreteCommand<Vec<Interface>> for ListInterfacesIfConfig {
                 .ok_or(anyhow::anyhow!("No valid interface name in '{}'", lines))?
                 .as_str().to_string();
 
-            let status = status_re.captures(lines)
-                .and_then(|cap| cap.get(1))
-                .ok_or(anyhow::anyhow!("No valid interface status in '{}'", lines))?
-                .as_str().to_string();
+            let flags: Vec<LinkFlag> = match flags_re.captures(lines).and_then(|cap| cap.get(1)) {
+                Some(flags) => flags.as_str().split(',').filter_map(LinkFlag::parse).collect(),
+                // busybox lists them on a line of their own, with the MTU
+                None => lines.lines()
+                    .find(|line| line.contains("MTU:"))
+                    .map(|line| line.split_whitespace().filter_map(LinkFlag::parse).collect())
+                    .unwrap_or_default(),
+            };
+            // ifconfig has no operational state, RUNNING is as close as it gets
+            let status = match flags.contains(&LinkFlag::Running) {
+                true => OperState::Up,
+                false => OperState::Down,
+            };
 
             let mac = mac_re.captures(lines)
                 .and_then(|cap| cap.get(1))
-                .ok_or(anyhow::anyhow!("No valid MAC address in '{}'", lines))?
-                .as_str().try_into()
+                .map(|mac| MAC::try_from(mac.as_str()))
+                .transpose()
                 .map_err(|e| anyhow::anyhow!("Invalid MAC address: {}", e))?;
 
-            let ip = ip_re.captures(lines)
-                .and_then(|cap| cap.get(1).or(cap.get(2)))
-                .ok_or(anyhow::anyhow!("No valid CIDR in '{}'", lines))?
-                .as_str().parse()
-                .map_err(|e| anyhow::anyhow!("Invalid CIDR: {}", e))?;
+            let addresses = lines.lines()
+                .map(str::trim)
+                .filter(|line| line.starts_with("inet ") || line.starts_with("inet6 "))
+                .map(parse_ifconfig_address)
+                .collect::<Result<Vec<Address>>>()?;
 
-            let mask = mask_re.captures(lines)
+            let mut interface = Interface::new(name, mac, addresses, status);
+            interface.mtu = mtu_re.captures(lines)
                 .and_then(|cap| cap.get(1))
-                .ok_or(anyhow::anyhow!("No valid mask in '{}'", lines))?
-                .as_str().parse()
-                .map_err(|e| anyhow::anyhow!("Invalid mask: {}", e))?;
+                .and_then(|mtu| mtu.as_str().parse().ok());
+            interface.link_type = if flags.contains(&LinkFlag::Loopback) {
+                LinkType::Loopback
+            } else if lines.contains(" ether ") || lines.contains("encap:Ethernet") {
+                LinkType::Ether
+            } else {
+                LinkType::Other
+            };
+            interface.flags = flags;
+            Ok(interface)
+        }).collect::<Result<Vec<Interface>>>()
+    }
+}
 
-            let cidr = CIDR::new(ip, mask);
+/// Either `inet 10.0.0.2  netmask 255.0.0.0  broadcast 10.255.255.255` with a dotted or hex mask,
+/// or the older `inet addr:10.0.0.2  Bcast:10.255.255.255  Mask:255.0.0.0` busybox still prints
+fn parse_ifconfig_address(line: &str) -> Result<Address> {
+    let (mut ip, mut prefix, mut mask, mut broadcast, mut scope) = (None, None, None, None, None);
+    let mut flags = Vec::new();
+    let mut words = line.split_whitespace().skip(1);
+    while let Some(word) = words.next() {
+        match word {
+            "netmask" => mask = words.next(),
+            "broadcast" => broadcast = words.next(),
+            "prefixlen" => prefix = words.next(),
+            "scopeid" => scope = words.next(),
+            "addr:" => ip = words.next(),
+            _ => match word.split_once(':') {
+                Some(("addr", value)) => ip = Some(value),
+                Some(("Bcast", value)) => broadcast = Some(value),
+                Some(("Mask", value)) => mask = Some(value),
+                Some(("Scope", value)) => scope = Some(value),
+                _ if ip.is_none() => ip = Some(word),
+                _ => flags.extend(AddressFlag::parse(word)),
+            },
+        }
+    }
 
-            Ok(Interface::new(name, mac, cidr, status))
-        }).collect::<Result<Vec<Interface>>>()
+    let ip = ip.ok_or(anyhow::anyhow!("No address in '{}'", line))?;
+    // BSD appends the zone to link-local addresses, busybox the prefix to IPv6 ones
+    let (ip, prefix) = match ip.split_once('/') {
+        Some((ip, prefix)) => (ip, Some(prefix)),
+        None => (ip, prefix),
+    };
+    let ip: IpAddr = ip.split('%').next().unwrap_or(ip).parse()
+        .map_err(|e| anyhow::anyhow!("Invalid address: {}", e))?;
+    let cidr = match (prefix, mask) {
+        (Some(prefix), _) => CIDR(ip, prefix.parse()?),
+        (None, Some(mask)) => CIDR::new(ip, parse_mask(mask)?),
+        (None, None) => CIDR::host(ip),
+    };
+
+    let mut address = Address::new(cidr);
+    address.broadcast = broadcast.and_then(|broadcast| broadcast.parse().ok());
+    if let Some(scope) = scope.and_then(AddressScope::parse) {
+        address.scope = scope;
+    }
+    address.flags = flags;
+    Ok(address)
+}
+
+/// `255.255.255.0` or, on the BSDs, `0xffffff00`
+fn parse_mask(mask: &str) -> Result<Ipv4Addr> {
+    match mask.strip_prefix("0x") {
+        Some(hex) => Ok(u32::from_str_radix(hex, 16)?.into()),
+        None => mask.parse().map_err(|e| anyhow::anyhow!("Invalid mask: {}", e)),
+    }
+}
+
+/// Links get a `--- link <name>` section each, the kernel address and route tables follow.
+/// `/proc/net/fib_trie` doesn't say which link an IPv4 address is on, `/proc/net/route` does.
+const SYSFS_SCRIPT: &str = r#"
+for dev in /sys/class/net/*; do
+    # Skips files like bonding_masters
+    [ -d "$dev" ] || continue
+    echo "--- link ${dev##*/}"
+    for attr in address operstate mtu type flags carrier speed duplex ifindex iflink; do
+        echo "$attr=$(cat "$dev/$attr" 2>/dev/null)"
+    done
+    master=$(readlink "$dev/master" 2>/dev/null); echo "master=${master##*/}"
+    grep -s DEVTYPE "$dev/uevent"
+done
+echo "--- fib_trie"; cat /proc/net/fib_trie 2>/dev/null
+echo "--- route"; cat /proc/net/route 2>/dev/null
+echo "--- if_inet6"; cat /proc/net/if_inet6 2>/dev/null
+true"#;
+
+/// `ARPHRD_LOOPBACK`, there is no route to tell which link the loopback addresses are on
+const LOOPBACK_TYPE: &str = "772";
+const ETHER_TYPE: &str = "1";
+
+/// Straight from the kernel, for hosts with neither `ip` nor `ifconfig`
+struct ListInterfacesSysfs;
+
+impl ConcreteCommand<Vec<Interface>> for ListInterfacesSysfs {
+    fn detection_command(&self) -> ShellCommand {
+        ShellCommand::new("ls").arg("/sys/class/net")
+    }
+
+    fn execution_command(&self) -> ShellCommand {
+        ShellCommand::script(SYSFS_SCRIPT)
+    }
+
+    fn parse_detection_output(&self, output: &CommandOutput) -> Result<bool> {
+        Ok(output.success() && !output.stdout.trim().is_empty())
+    }
+
+    fn supports(&self, facts: &HostFacts) -> Option<bool> {
+        (!facts.is_linux()).then_some(false)
+    }
+
+    fn parse_execution_output(&self, output: &str) -> Result<Vec<Interface>> {
+        let mut sections: Vec<(&str, Vec<&str>)> = Vec::new();
+        for line in output.lines() {
+            match (line.strip_prefix("--- "), sections.last_mut()) {
+                (Some(name), _) => sections.push((name, Vec::new())),
+                (None, Some((_, lines))) => lines.push(line),
+                (None, None) => {}
+            }
+        }
+        let table = |name| {
+            sections.iter()
+                .find(|(section, _)| *section == name)
+                .map_or(&[][..], |(_, lines)| lines.as_slice())
+        };
+        let fib = FibTrie::parse(table("fib_trie"));
+        let routes = parse_proc_route(table("route"));
+        let mut ipv6 = parse_if_inet6(table("if_inet6"))?;
+
+        let links: Vec<(&str, HashMap<_, _>)> = sections.iter()
+            .filter_map(|(section, lines)| {
+                let attributes = lines.iter().filter_map(|line| line.split_once('=')).collect();
+                Some((section.strip_prefix("link ")?, attributes))
+            })
+            .collect();
+        let names_by_index: HashMap<_, _> = links.iter()
+            .filter_map(|(name, attributes)| Some((*attributes.get("ifindex")?, *name)))
+            .collect();
+
+        let interfaces = links.iter().map(|(name, attributes)| {
+            let name = *name;
+            let loopback = attributes.get("type") == Some(&LOOPBACK_TYPE);
+            let mac = attributes.get("address").and_then(|address| MAC::try_from(*address).ok());
+            let status = attributes.get("operstate").unwrap_or(&"unknown");
+
+            let mut addresses: Vec<Address> = fib.locals.iter()
+                .filter(|ip| match ip.is_loopback() {
+                    true => loopback,
+                    false => route_link(**ip, &routes) == Some(name),
+                })
+                .map(|ip| fib.address(*ip))
+                .collect();
+            addresses.extend(ipv6.remove(name).unwrap_or_default());
+
+            let mut interface = Interface::new(name.to_string(), mac, addresses, OperState::parse(status));
+            interface.mtu = attributes.get("mtu").and_then(|mtu| mtu.parse().ok());
+            let flags = attributes.get("flags")
+                .and_then(|flags| u32::from_str_radix(flags.trim_start_matches("0x"), 16).ok())
+                .unwrap_or_default();
+            interface.flags = LinkFlag::BITS.iter()
+                .filter(|(bit, _)| flags & bit != 0)
+                .map(|(_, flag)| *flag)
+                .collect();
+            if attributes.get("carrier") == Some(&"1") {
+                interface.flags.push(LinkFlag::LowerUp);
+            }
+            interface.link_type = attributes.get("DEVTYPE")
+                .and_then(|kind| LinkType::parse(kind))
+                .unwrap_or(match attributes.get("type") {
+                    Some(&ETHER_TYPE) => LinkType::Ether,
+                    Some(&LOOPBACK_TYPE) => LinkType::Loopback,
+                    _ => LinkType::Other,
+                });
+            interface.set_speed(attributes);
+            interface.master = attributes.get("master").filter(|master| !master.is_empty()).map(|master| master.to_string());
+            interface.parent = match (attributes.get("ifindex"), attributes.get("iflink")) {
+                (Some(index), Some(link)) if index != link => {
+                    Some(names_by_index.get(link).map_or(format!("if{}", link), |name| name.to_string()))
+                }
+                _ => None,
+            };
+            interface
+        });
+        Ok(interfaces.collect())
+    }
+}
+
+/// The local addresses, networks and broadcast addresses the kernel routes for
+#[derive(Default)]
+struct FibTrie {
+    locals: Vec<Ipv4Addr>,
+    networks: Vec<(Ipv4Addr, u8)>,
+    broadcasts: HashSet<Ipv4Addr>,
+}
+
+impl FibTrie {
+    /// Each `|-- 192.168.0.3` leaf is followed by its prefixes, like `/32 host LOCAL`.
+    /// The main and local tables are both listed, so the leaves come twice.
+    fn parse(lines: &[&str]) -> Self {
+        let mut fib = Self::default();
+        let mut leaf = None;
+        for line in lines.iter().map(|line| line.trim()) {
+            if let Some(ip) = line.strip_prefix("|-- ") {
+                leaf = ip.parse().ok();
+                continue;
+            }
+            let (Some(ip), Some(entry)) = (leaf, line.strip_prefix('/')) else {
+                continue;
+            };
+            let words: Vec<_> = entry.split_whitespace().collect();
+            let Some(prefix) = words.first().and_then(|prefix| prefix.parse::<u8>().ok()) else {
+                continue;
+            };
+            match (prefix, words.last()) {
+                (32, Some(&"LOCAL")) if !fib.locals.contains(&ip) => fib.locals.push(ip),
+                (32, Some(&"BROADCAST")) => {
+                    fib.broadcasts.insert(ip);
+                }
+                (1..=31, Some(&"UNICAST") | Some(&"LOCAL")) => fib.networks.push((ip, prefix)),
+                _ => {}
+            }
+        }
+        fib
+    }
+
+    /// The prefix is the longest network the address is in
+    fn address(&self, ip: Ipv4Addr) -> Address {
+        let prefix = self.networks.iter()
+            .filter(|(network, prefix)| u32::from(ip) & prefix_mask(*prefix) == u32::from(*network))
+            .map(|(_, prefix)| *prefix)
+            .max()
+            .unwrap_or(32);
+        let mut address = Address::new(CIDR(IpAddr::V4(ip), prefix));
+        let broadcast = Ipv4Addr::from(u32::from(ip) | !prefix_mask(prefix));
+        address.broadcast = (prefix < 31 && self.broadcasts.contains(&broadcast)).then_some(broadcast);
+        address
+    }
+}
+
+fn prefix_mask(prefix: u8) -> u32 {
+    u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0)
+}
+
+/// A route straight to a link: its name, destination and mask
+type LinkRoute = (String, u32, u32);
+
+/// Only the routes without a gateway, addresses are in their network
+fn parse_proc_route(lines: &[&str]) -> Vec<LinkRoute> {
+    lines.iter().skip(1).filter_map(|line| {
+        let fields: Vec<_> = line.split_whitespace().collect();
+        let (iface, destination, gateway, mask) = (fields.first()?, fields.get(1)?, fields.get(2)?, fields.get(7)?);
+        let [destination, gateway, mask] = [destination, gateway, mask].map(|field| u32::from_str_radix(field, 16).ok());
+        let (destination, mask) = (destination?, mask?);
+        if gateway? != 0 {
+            return None;
+        }
+        // The fields are in the host's byte order, only a valid mask tells which it is
+        match mask.leading_ones() + mask.trailing_zeros() >= 32 {
+            true => Some((iface.to_string(), destination, mask)),
+            false => Some((iface.to_string(), destination.swap_bytes(), mask.swap_bytes())),
+        }
+    }).collect()
+}
+
+/// The link whose connected route has the most specific network containing `ip`.
+/// Addresses without such a route, like a /32 on `lo` or one added with `noprefixroute`,
+/// are on no link as far as procfs tells, so they are left out.
+fn route_link(ip: Ipv4Addr, routes: &[LinkRoute]) -> Option<&str> {
+    routes.iter()
+        .filter(|(_, destination, mask)| u32::from(ip) & mask == *destination)
+        .max_by_key(|(_, _, mask)| mask.leading_ones())
+        .map(|(iface, _, _)| iface.as_str())
+}
+
+/// Like `fe800000000000000a0027fffe4e66a1 02 40 20 80     eth0`: address, index, prefix, scope,
+/// the low byte of the flags then the link name, all in hex
+fn parse_if_inet6(lines: &[&str]) -> Result<HashMap<String, Vec<Address>>> {
+    let mut addresses = HashMap::<_, Vec<_>>::new();
+    for line in lines {
+        let [ip, _, prefix, scope, flags, name] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+            continue;
+        };
+        let ip = Ipv6Addr::from(u128::from_str_radix(ip, 16)?);
+        let mut address = Address::new(CIDR(IpAddr::V6(ip), u8::from_str_radix(prefix, 16)?));
+        address.scope = match u8::from_str_radix(scope, 16)? {
+            0x10 => AddressScope::Host,
+            0x20 => AddressScope::Link,
+            0x40 => AddressScope::Site,
+            _ => AddressScope::Global,
+        };
+        let flags = u8::from_str_radix(flags, 16)?;
+        address.flags = [
+            (0x01, AddressFlag::Temporary),
+            (0x20, AddressFlag::Deprecated),
+            (0x40, AddressFlag::Tentative),
+        ]
+        .into_iter()
+        .filter_map(|(bit, flag)| (flags & bit != 0).then_some(flag))
+        .collect();
+        // Like `ip`, anything not permanent came from autoconfiguration
+        if flags & 0x80 == 0 {
+            address.flags.insert(0, AddressFlag::Dynamic);
+        }
+        addresses.entry(name.to_string()).or_default().push(address);
     }
+    Ok(addresses)
 }
 
 #[cfg(test)]
 mod test {
     use std::collections::HashMap;
 
-    use crate::ssh::command::{test::MockCommandExecutor, CommandExecutor};
+    use crate::ssh::command::{test::MockCommandExecutor, CommandError, CommandExecutor};
 
     use super::*;
 
+    /// One type per implementation, detection is cached by type
     #[derive(Debug)]
-    struct MockCommand {
-        implementation: usize,
-    }
+    struct MockCommand<const IMPLEMENTATION: usize>;
 
-    impl MockCommand {
-        const IMPLEMENTATIONS: [&'static dyn ConcreteCommand<Vec<Interface>>; 2] =
-            [&ListInterfacesIp {}, &ListInterfacesIfConfig {}];
-
-        pub fn new(implementation: usize) -> Self {
-            Self { implementation }
-        }
-    }
+    const IMPLEMENTATIONS: [&'static dyn ConcreteCommand<Vec<Interface>>; 4] = [
+        &ListInterfacesIp {},
+        &ListInterfacesIfConfig {},
+        &ListInterfacesIpJson {},
+        &ListInterfacesSysfs {},
+    ];
 
-    impl VirtualCommand<Vec<Interface>, 1> for MockCommand {
+    impl<const IMPLEMENTATION: usize> VirtualCommand<Vec<Interface>, 1> for MockCommand<IMPLEMENTATION> {
         fn implementations(&self) -> [&'static dyn ConcreteCommand<Vec<Interface>>; 1] {
-            [Self::IMPLEMENTATIONS[self.implementation]]
+            [IMPLEMENTATIONS[IMPLEMENTATION]]
         }
     }
 
@@ -245,6 +1060,8 @@ mod test {
             "    link/ether bc:24:11:8c:3e:4b brd ff:ff:ff:ff:ff:ff link-netnsid 0",
             "    inet 192.168.0.3/24 brd 192.168.0.255 scope global eth0",
             "        valid_lft forever preferred_lft forever",
+            "    inet 192.168.0.50/24 brd 192.168.0.255 scope global secondary dynamic eth0",
+            "        valid_lft 86350sec preferred_lft 86350sec",
             "    inet6 fe80::be24:11ff:fe8c:3e4b/64 scope link",
             "        valid_lft forever preferred_lft forever"
         ].join("\n");
@@ -257,17 +1074,43 @@ mod test {
                 ].iter()
                     .map(|(k, v)| (k.to_string(), v.to_string())),
             ));
-        let command = MockCommand::new(0);
+        let command = MockCommand::<0>;
         let interfaces = command.execute(&executor).await;
         assert!(interfaces.is_ok(), "{:?}", interfaces.err());
         let interfaces = interfaces.unwrap();
         assert_eq!(interfaces.len(), 2);
         assert_eq!(interfaces[0].name, "lo");
-        assert_eq!(interfaces[0].mac, MAC::try_from("00:00:00:00:00:00").unwrap());
-        assert_eq!(interfaces[0].status, "UNKNOWN");
+        assert_eq!(interfaces[0].mac, Some(MAC::try_from("00:00:00:00:00:00").unwrap()));
+        assert_eq!(interfaces[0].status, OperState::Unknown);
+        assert_eq!(interfaces[0].mtu, Some(65536));
+        assert_eq!(interfaces[0].link_type, LinkType::Loopback);
         assert_eq!(interfaces[1].name, "eth0");
-        assert_eq!(interfaces[1].mac, MAC::try_from("bc:24:11:8c:3e:4b").unwrap());
-        assert_eq!(interfaces[1].status, "UP");
+        assert_eq!(interfaces[1].mac, Some(MAC::try_from("bc:24:11:8c:3e:4b").unwrap()));
+        assert_eq!(interfaces[1].status, OperState::Up);
+        assert_eq!(interfaces[1].parent.as_deref(), Some("if12"));
+        assert_eq!(
+            interfaces[1].flags,
+            [LinkFlag::Broadcast, LinkFlag::Multicast, LinkFlag::Up, LinkFlag::LowerUp]
+        );
+        assert_eq!(interfaces[1].link_type, LinkType::Ether);
+        assert_eq!(interfaces[1].master, None);
+
+        let lo = &interfaces[0].addresses;
+        assert_eq!(lo.len(), 2);
+        assert_eq!(lo[0].cidr, CIDR::try_from("127.0.0.1/8").unwrap());
+        assert_eq!(lo[0].scope, AddressScope::Host);
+        assert_eq!(lo[1].family, AddressFamily::Ipv6);
+        assert_eq!(lo[1].flags, [AddressFlag::NoPrefixRoute]);
+
+        let eth0 = &interfaces[1].addresses;
+        assert_eq!(eth0.len(), 3);
+        assert_eq!(eth0[0].broadcast, Some(Ipv4Addr::new(192, 168, 0, 255)));
+        assert_eq!(eth0[0].lifetime, Some(Lifetime { valid: None, preferred: None }));
+        assert_eq!(eth0[1].cidr, CIDR::try_from("192.168.0.50/24").unwrap());
+        assert_eq!(eth0[1].flags, [AddressFlag::Secondary, AddressFlag::Dynamic]);
+        assert_eq!(eth0[1].lifetime, Some(Lifetime { valid: Some(86350), preferred: Some(86350) }));
+        assert_eq!(eth0[2].scope, AddressScope::Link);
+        assert_eq!(eth0[2].broadcast, None);
     }
 
     #[tokio::test]
@@ -304,14 +1147,273 @@ mod test {
                 ].iter().map(|(k, v)| (k.to_string(), v.to_string()))
             ));
 
-        let command = MockCommand::new(1);
+        let command = MockCommand::<1>;
         let interfaces = command.execute(&executor).await.unwrap();
         assert_eq!(interfaces.len(), 2);
         assert_eq!(interfaces[0].name, "br-2a665e4dbc69");
-        assert_eq!(interfaces[0].mac, MAC::try_from("02:42:7c:8b:fb:54").unwrap());
-        assert_eq!(interfaces[0].status, "UP");
+        assert_eq!(interfaces[0].mac, Some(MAC::try_from("02:42:7c:8b:fb:54").unwrap()));
+        assert_eq!(interfaces[0].status, OperState::Up);
+        assert_eq!(interfaces[0].mtu, Some(1500));
+        assert_eq!(interfaces[0].link_type, LinkType::Ether);
         assert_eq!(interfaces[1].name, "br-569c181e089f");
-        assert_eq!(interfaces[1].mac, MAC::try_from("02:42:bb:47:77:14").unwrap());
-        assert_eq!(interfaces[1].status, "UP");
+        assert_eq!(interfaces[1].mac, Some(MAC::try_from("02:42:bb:47:77:14").unwrap()));
+        // UP but not RUNNING, no carrier
+        assert_eq!(interfaces[1].status, OperState::Down);
+
+        let addresses = &interfaces[0].addresses;
+        assert_eq!(addresses.len(), 2);
+        assert_eq!(addresses[0].cidr, CIDR::try_from("172.22.0.1/16").unwrap());
+        assert_eq!(addresses[0].broadcast, Some(Ipv4Addr::new(172, 22, 255, 255)));
+        assert_eq!(addresses[0].scope, AddressScope::Global);
+        assert_eq!(addresses[1].cidr, CIDR::try_from("fe80::42:7cff:fe8b:fb54/64").unwrap());
+        assert_eq!(addresses[1].scope, AddressScope::Link);
+        assert_eq!(interfaces[1].addresses.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_list_interfaces_ip_json() {
+        let output = r#"[
+            {"ifindex":1,"ifname":"lo","flags":["LOOPBACK","UP","LOWER_UP"],"mtu":65536,"operstate":"UNKNOWN",
+             "link_type":"loopback","address":"00:00:00:00:00:00","broadcast":"00:00:00:00:00:00",
+             "addr_info":[{"family":"inet","local":"127.0.0.1","prefixlen":8,"scope":"host","label":"lo",
+                           "valid_life_time":4294967295,"preferred_life_time":4294967295}]},
+            {"ifindex":3,"ifname":"eth0.10","link":"eth0","master":"br0","flags":["BROADCAST","MULTICAST","UP","LOWER_UP"],"mtu":1500,
+             "operstate":"UP","link_type":"ether","address":"bc:24:11:8c:3e:4b","broadcast":"ff:ff:ff:ff:ff:ff",
+             "altnames":["enp0s3.10"],"linkinfo":{"info_kind":"vlan","info_data":{"protocol":"802.1Q","id":10}},
+             "addr_info":[{"family":"inet","local":"10.10.0.5","prefixlen":24,"broadcast":"10.10.0.255","scope":"global",
+                           "dynamic":true,"label":"eth0.10","valid_life_time":3540,"preferred_life_time":3540},
+                          {"family":"inet6","local":"fe80::be24:11ff:fe8c:3e4b","prefixlen":64,"scope":"link",
+                           "valid_life_time":4294967295,"preferred_life_time":4294967295}]},
+            {"ifindex":4,"ifname":"wg0","flags":["POINTOPOINT","NOARP","UP","LOWER_UP"],"mtu":1420,"operstate":"UNKNOWN",
+             "link_type":"none","linkinfo":{"info_kind":"wireguard"},
+             "addr_info":[{"family":"inet","local":"10.8.0.2","prefixlen":32,"scope":"global","label":"wg0",
+                           "valid_life_time":4294967295,"preferred_life_time":4294967295},{}]}
+        ]
+--- speed
+eth0.10 speed=1000 duplex=full
+lo speed= duplex=
+wg0 speed= duplex="#;
+
+        let executor = MockCommandExecutor::new(HashMap::from([
+            ("ip -j -d addr show lo".to_string(), "[{}]".to_string()),
+            (ShellCommand::script(IP_JSON_SCRIPT).to_string(), output.to_string()),
+        ]));
+        let interfaces = MockCommand::<2>.execute(&executor).await.unwrap();
+        assert_eq!(interfaces.len(), 3);
+        assert_eq!(interfaces[0].mac, Some(MAC::try_from("00:00:00:00:00:00").unwrap()));
+        assert_eq!(interfaces[0].addresses[0].lifetime, Some(Lifetime { valid: None, preferred: None }));
+
+        let vlan = &interfaces[1];
+        assert_eq!(vlan.name, "eth0.10");
+        assert_eq!(vlan.status, OperState::Up);
+        assert_eq!(vlan.link_type, LinkType::Vlan);
+        assert_eq!(vlan.parent.as_deref(), Some("eth0"));
+        assert_eq!(vlan.master.as_deref(), Some("br0"));
+        assert_eq!(vlan.mtu, Some(1500));
+        assert_eq!((vlan.speed, vlan.duplex), (Some(1000), Some(Duplex::Full)));
+        assert_eq!(vlan.addresses.len(), 2);
+        assert_eq!(vlan.addresses[0].cidr, CIDR::try_from("10.10.0.5/24").unwrap());
+        assert_eq!(vlan.addresses[0].broadcast, Some(Ipv4Addr::new(10, 10, 0, 255)));
+        assert_eq!(vlan.addresses[0].flags, [AddressFlag::Dynamic]);
+        assert_eq!(vlan.addresses[0].lifetime, Some(Lifetime { valid: Some(3540), preferred: Some(3540) }));
+        assert_eq!(vlan.addresses[1].scope, AddressScope::Link);
+
+        let wireguard = &interfaces[2];
+        assert_eq!(wireguard.name, "wg0");
+        assert_eq!(wireguard.mac, None);
+        assert_eq!(wireguard.link_type, LinkType::Wireguard);
+        assert_eq!(wireguard.flags, [LinkFlag::PointToPoint, LinkFlag::NoArp, LinkFlag::Up, LinkFlag::LowerUp]);
+        assert_eq!(wireguard.speed, None);
+        assert_eq!(wireguard.addresses.len(), 1);
+        assert_eq!(wireguard.addresses[0].cidr, CIDR::try_from("10.8.0.2/32").unwrap());
+
+        let old = MockCommandExecutor::new(HashMap::new());
+        assert!(MockCommand::<2>.execute(&old).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_list_interfaces_sysfs() {
+        let output = [
+            "--- link eth0",
+            "address=bc:24:11:8c:3e:4b",
+            "operstate=up",
+            "mtu=1500",
+            "type=1",
+            "flags=0x1003",
+            "carrier=1",
+            "speed=10000",
+            "duplex=full",
+            "ifindex=2",
+            "iflink=12",
+            "master=",
+            "--- link lo",
+            "address=00:00:00:00:00:00",
+            "operstate=unknown",
+            "mtu=65536",
+            "type=772",
+            "flags=0x9",
+            "carrier=1",
+            "speed=",
+            "duplex=",
+            "ifindex=1",
+            "iflink=1",
+            "master=",
+            "--- link wg0",
+            "address=",
+            "operstate=unknown",
+            "mtu=1420",
+            "type=65534",
+            "flags=0x91",
+            "carrier=1",
+            "speed=",
+            "duplex=",
+            "ifindex=4",
+            "iflink=4",
+            "master=",
+            "DEVTYPE=wireguard",
+            "--- fib_trie",
+            "Main:",
+            "  +-- 0.0.0.0/0 3 0 5",
+            "     |-- 0.0.0.0",
+            "        /0 universe UNICAST",
+            "     +-- 10.8.0.0/24 2 0 2",
+            "        |-- 10.8.0.0",
+            "           /24 link UNICAST",
+            "        |-- 10.8.0.2",
+            "           /32 host LOCAL",
+            "     +-- 192.168.0.0/24 2 0 2",
+            "        |-- 192.168.0.0",
+            "           /24 link UNICAST",
+            "        |-- 192.168.0.3",
+            "           /32 host LOCAL",
+            "        |-- 192.168.0.255",
+            "           /32 link BROADCAST",
+            "Local:",
+            "  +-- 127.0.0.0/8 2 0 2",
+            "     |-- 127.0.0.0",
+            "        /8 host LOCAL",
+            "     |-- 127.0.0.1",
+            "        /32 host LOCAL",
+            "  |-- 192.168.0.3",
+            "     /32 host LOCAL",
+            "--- route",
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT",
+            "eth0\t00000000\t0100A8C0\t0003\t0\t0\t0\t00000000\t0\t0\t0",
+            "eth0\t0000A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0",
+            "wg0\t0000080A\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0",
+            "--- if_inet6",
+            "00000000000000000000000000000001 01 80 10 80       lo",
+            "fe80000000000000be2411fffe8c3e4b 02 40 20 80     eth0",
+            "20010db8000000000000000000000042 02 40 00 00     eth0",
+        ].join("\n");
+
+        let executor = MockCommandExecutor::new(HashMap::from([
+            ("ls /sys/class/net".to_string(), "eth0\nlo\nwg0\n".to_string()),
+            (ShellCommand::script(SYSFS_SCRIPT).to_string(), output),
+        ]));
+        let interfaces = MockCommand::<3>.execute(&executor).await.unwrap();
+        assert_eq!(interfaces.len(), 3);
+
+        let eth0 = &interfaces[0];
+        assert_eq!(eth0.mac, Some(MAC::try_from("bc:24:11:8c:3e:4b").unwrap()));
+        assert_eq!(eth0.status, OperState::Up);
+        assert_eq!(eth0.mtu, Some(1500));
+        assert_eq!(eth0.flags, [LinkFlag::Up, LinkFlag::Broadcast, LinkFlag::Multicast, LinkFlag::LowerUp]);
+        assert_eq!(eth0.link_type, LinkType::Ether);
+        assert_eq!((eth0.speed, eth0.duplex), (Some(10000), Some(Duplex::Full)));
+        assert_eq!(eth0.parent.as_deref(), Some("if12"));
+        assert_eq!(eth0.master, None);
+        assert_eq!(eth0.addresses.len(), 3);
+        assert_eq!(eth0.addresses[0].cidr, CIDR::try_from("192.168.0.3/24").unwrap());
+        assert_eq!(eth0.addresses[0].broadcast, Some(Ipv4Addr::new(192, 168, 0, 255)));
+        assert_eq!(eth0.addresses[1].scope, AddressScope::Link);
+        assert_eq!(eth0.addresses[1].cidr, CIDR::try_from("fe80::be24:11ff:fe8c:3e4b/64").unwrap());
+        assert_eq!(eth0.addresses[2].scope, AddressScope::Global);
+        assert_eq!(eth0.addresses[2].flags, [AddressFlag::Dynamic]);
+
+        let lo = &interfaces[1];
+        assert_eq!(lo.status, OperState::Unknown);
+        assert_eq!(lo.link_type, LinkType::Loopback);
+        assert_eq!(lo.parent, None);
+        assert_eq!(lo.addresses.len(), 2);
+        assert_eq!(lo.addresses[0].cidr, CIDR::try_from("127.0.0.1/8").unwrap());
+        assert_eq!(lo.addresses[1].scope, AddressScope::Host);
+
+        let wg0 = &interfaces[2];
+        assert_eq!(wg0.mac, None);
+        assert_eq!(wg0.link_type, LinkType::Wireguard);
+        assert_eq!(wg0.flags, [LinkFlag::Up, LinkFlag::PointToPoint, LinkFlag::NoArp, LinkFlag::LowerUp]);
+        assert_eq!(wg0.addresses, [Address::new(CIDR::try_from("10.8.0.2/24").unwrap())]);
+    }
+
+    #[test]
+    fn test_route_link_unrouted_address() {
+        let routes = parse_proc_route(&[
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT",
+            "eth0\t00000000\t0100A8C0\t0003\t0\t0\t0\t00000000\t0\t0\t0",
+            "eth0\t0000A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0",
+        ]);
+        assert_eq!(route_link(Ipv4Addr::new(192, 168, 0, 3), &routes), Some("eth0"));
+        // The default route has a gateway, it doesn't claim every address
+        assert_eq!(route_link(Ipv4Addr::new(10, 53, 0, 1), &routes), None);
+    }
+
+    #[test]
+    fn test_parse_ifconfig_address_styles() {
+        let busybox = parse_ifconfig_address("inet addr:10.0.0.2  Bcast:10.0.0.255  Mask:255.255.255.0").unwrap();
+        assert_eq!(busybox.cidr, CIDR::try_from("10.0.0.2/24").unwrap());
+        assert_eq!(busybox.broadcast, Some(Ipv4Addr::new(10, 0, 0, 255)));
+
+        let busybox6 = parse_ifconfig_address("inet6 addr: fe80::a00:27ff:fe4e:66a1/64 Scope:Link").unwrap();
+        assert_eq!(busybox6.cidr, CIDR::try_from("fe80::a00:27ff:fe4e:66a1/64").unwrap());
+        assert_eq!(busybox6.scope, AddressScope::Link);
+
+        let bsd = parse_ifconfig_address("inet6 fe80::1%lo0 prefixlen 64 scopeid 0x2").unwrap();
+        assert_eq!(bsd.cidr, CIDR::try_from("fe80::1/64").unwrap());
+        assert_eq!(bsd.scope, AddressScope::Link);
+
+        let bsd = parse_ifconfig_address("inet 192.168.1.2 netmask 0xffffff00 broadcast 192.168.1.255").unwrap();
+        assert_eq!(bsd.cidr, CIDR::try_from("192.168.1.2/24").unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_detection_is_cached() {
+        let executor = MockCommandExecutor::new(HashMap::from([
+            ("ip -V".to_string(), "ip utility, iproute2-6.1.0\n".to_string()),
+            ("ip a".to_string(), String::new()),
+        ]));
+        ListInterfaces.execute(&executor).await.unwrap();
+        ListInterfaces.execute(&executor).await.unwrap();
+        assert_eq!(*executor.history.lock().unwrap(), ["ip -j -d addr show lo", "ip -V", "ifconfig -s lo", "ls /sys/class/net", "ip a", "ip a"]);
+
+        executor.detection_cache().unwrap().clear();
+        executor.history.lock().unwrap().clear();
+        ListInterfaces.execute(&executor).await.unwrap();
+        assert_eq!(*executor.history.lock().unwrap(), ["ip -j -d addr show lo", "ip -V", "ifconfig -s lo", "ls /sys/class/net", "ip a"]);
+    }
+
+    #[tokio::test]
+    async fn test_stale_detection_is_redone() {
+        let executor = MockCommandExecutor::new(HashMap::from([
+            ("ip -V".to_string(), "ip utility, iproute2-6.1.0\n".to_string()),
+            ("ip a".to_string(), String::new()),
+        ]));
+        // Like ifconfig having been uninstalled since it was detected
+        executor.detection_cache().unwrap().set(std::any::TypeId::of::<ListInterfaces>(), 2);
+        ListInterfaces.execute(&executor).await.unwrap();
+        ListInterfaces.execute(&executor).await.unwrap();
+        let history = executor.history.lock().unwrap();
+        assert_eq!(history[0], "ifconfig");
+        assert_eq!(history[1..], ["ip -j -d addr show lo", "ip -V", "ifconfig -s lo", "ls /sys/class/net", "ip a", "ip a"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_interfaces_no_implementation() {
+        let executor = MockCommandExecutor::new(HashMap::new());
+        let err = ListInterfaces.execute(&executor).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CommandError>(),
+            Some(CommandError::NoSuitableImplementation)
+        ));
     }
 }
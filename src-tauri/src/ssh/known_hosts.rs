@@ -0,0 +1,265 @@
+use std::{
+    mem::discriminant,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use data_encoding::BASE64;
+use hmac::{Hmac, Mac};
+use russh_keys::key::PublicKey;
+use serde::Serialize;
+use sha1::Sha1;
+
+#[derive(Debug, PartialEq)]
+pub enum HostKeyStatus {
+    Known,
+    Unknown,
+    Changed { path: PathBuf, line: usize },
+    Revoked { path: PathBuf, line: usize },
+}
+
+#[derive(Debug)]
+pub enum HostKeyError {
+    Changed { host: String, port: u16, fingerprint: String, path: PathBuf, line: usize },
+    Revoked { host: String, port: u16, fingerprint: String },
+    Rejected { host: String, port: u16 },
+}
+
+impl std::fmt::Display for HostKeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            HostKeyError::Changed { host, port, fingerprint, path, line } => write!(
+                f,
+                "Host key for {}:{} has changed to {}, it does not match {}:{}",
+                host, port, fingerprint, path.display(), line
+            ),
+            HostKeyError::Revoked { host, port, fingerprint } => {
+                write!(f, "Host key {} for {}:{} has been revoked", fingerprint, host, port)
+            }
+            HostKeyError::Rejected { host, port } => {
+                write!(f, "Host key for {}:{} was rejected", host, port)
+            }
+        }
+    }
+}
+
+impl std::error::Error for HostKeyError {}
+
+/// What the UI needs to decide whether to trust a host it has never seen
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HostKeyPrompt {
+    host: String,
+    port: u16,
+    key_type: String,
+    fingerprint: String,
+}
+
+impl HostKeyPrompt {
+    pub fn new(host: &str, port: u16, key: &PublicKey) -> Self {
+        Self {
+            host: host.to_string(),
+            port,
+            key_type: key.name().to_string(),
+            fingerprint: fingerprint(key),
+        }
+    }
+}
+
+pub fn fingerprint(key: &PublicKey) -> String {
+    format!("SHA256:{}", key.fingerprint())
+}
+
+/// OpenSSH-compatible known_hosts lookup over the user's file and our own.
+/// Keys accepted from the UI are only ever written to the app-owned file.
+pub struct KnownHosts {
+    user_file: Option<PathBuf>,
+    app_file: PathBuf,
+}
+
+impl KnownHosts {
+    pub fn new(app_file: PathBuf) -> Self {
+        Self {
+            user_file: tauri::api::path::home_dir().map(|home| home.join(".ssh").join("known_hosts")),
+            app_file,
+        }
+    }
+
+    pub fn check(&self, host: &str, port: u16, key: &PublicKey) -> Result<HostKeyStatus> {
+        let mut status = HostKeyStatus::Unknown;
+        for path in self.user_file.iter().chain(Some(&self.app_file)) {
+            let contents = match std::fs::read_to_string(path) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).with_context(|| format!("Failed to read '{}'", path.display())),
+            };
+            match check_entries(&contents, path, host, port, key) {
+                HostKeyStatus::Unknown => {}
+                HostKeyStatus::Known => status = HostKeyStatus::Known,
+                // A mismatch or revocation anywhere wins over a match elsewhere
+                other => return Ok(other),
+            }
+        }
+        Ok(status)
+    }
+
+    pub fn learn(&self, host: &str, port: u16, key: &PublicKey) -> Result<()> {
+        russh_keys::learn_known_hosts_path(host, port, key, &self.app_file)
+            .with_context(|| format!("Failed to write '{}'", self.app_file.display()))
+    }
+}
+
+fn check_entries(contents: &str, path: &Path, host: &str, port: u16, key: &PublicKey) -> HostKeyStatus {
+    let host_port = if port == 22 { host.to_string() } else { format!("[{}]:{}", host, port) };
+    let mut status = HostKeyStatus::Unknown;
+
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = line.split_whitespace();
+        let (marker, hosts) = match fields.next() {
+            Some(marker) if marker.starts_with('@') => (Some(marker), fields.next()),
+            hosts => (None, hosts),
+        };
+        let (Some(hosts), Some(_key_type), Some(recorded)) = (hosts, fields.next(), fields.next()) else {
+            continue;
+        };
+        // We don't do certificates
+        if marker == Some("@cert-authority") || !match_hosts(hosts, &host_port) {
+            continue;
+        }
+        // Unsupported key algorithms can never be what the server presented
+        let Ok(recorded) = russh_keys::parse_public_key_base64(recorded) else {
+            continue;
+        };
+        let line = i + 1;
+
+        if marker == Some("@revoked") {
+            if recorded == *key {
+                return HostKeyStatus::Revoked { path: path.to_path_buf(), line };
+            }
+        } else if recorded == *key {
+            status = HostKeyStatus::Known;
+        } else if discriminant(&recorded) == discriminant(key) {
+            status = HostKeyStatus::Changed { path: path.to_path_buf(), line };
+        }
+    }
+
+    status
+}
+
+fn match_hosts(patterns: &str, host: &str) -> bool {
+    let mut matched = false;
+    for pattern in patterns.split(',') {
+        if let Some(hashed) = pattern.strip_prefix("|1|") {
+            matched |= match_hashed(hashed, host);
+        } else if let Some(negated) = pattern.strip_prefix('!') {
+            if match_pattern(negated, host) {
+                return false;
+            }
+        } else {
+            matched |= match_pattern(pattern, host);
+        }
+    }
+    matched
+}
+
+fn match_hashed(hashed: &str, host: &str) -> bool {
+    let Some((salt, hash)) = hashed.split_once('|') else {
+        return false;
+    };
+    let (Ok(salt), Ok(hash)) = (BASE64.decode(salt.as_bytes()), BASE64.decode(hash.as_bytes())) else {
+        return false;
+    };
+    let Ok(mac) = Hmac::<Sha1>::new_from_slice(&salt) else {
+        return false;
+    };
+    mac.chain_update(host.as_bytes()).verify_slice(&hash).is_ok()
+}
+
+/// OpenSSH-style glob match (`*` and `?`), case insensitive
+pub fn match_pattern(pattern: &str, value: &str) -> bool {
+    fn matches(pattern: &[u8], value: &[u8]) -> bool {
+        match (pattern.first(), value.first()) {
+            (None, None) => true,
+            (Some(b'*'), _) => {
+                matches(&pattern[1..], value) || (!value.is_empty() && matches(pattern, &value[1..]))
+            }
+            (Some(b'?'), Some(_)) => matches(&pattern[1..], &value[1..]),
+            (Some(p), Some(v)) if p.eq_ignore_ascii_case(v) => matches(&pattern[1..], &value[1..]),
+            _ => false,
+        }
+    }
+    matches(pattern.as_bytes(), value.as_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KEY: &str = "AAAAC3NzaC1lZDI1NTE5AAAAINwJ/li43eAMKfR8rj/CDdU/WGbsTIaENZq62cPnwaqT";
+    const OTHER_KEY: &str = "AAAAC3NzaC1lZDI1NTE5AAAAINStYMBsaKzO414SsXriodSvOc77pUlAcdFMaCWNTt/V";
+    const ECDSA_KEY: &str = "AAAAE2VjZHNhLXNoYTItbmlzdHAyNTYAAAAIbmlzdHAyNTYAAABBBBJWUZOYAEfHtvCTAV0nGBvcYhTe3t+FrBtDX9wlpOlCLus9VXalYHJ17FscFCyH/vqZlXBvc+apU8oyg/Ou0pQ=";
+
+    fn check(contents: &str, host: &str, port: u16) -> HostKeyStatus {
+        let key = russh_keys::parse_public_key_base64(KEY).unwrap();
+        check_entries(contents, Path::new("known_hosts"), host, port, &key)
+    }
+
+    #[test]
+    fn test_known_hosts_match() {
+        let contents = [
+            "# comment".to_string(),
+            format!("pve.lan,192.168.0.2 ssh-ed25519 {}", OTHER_KEY),
+            format!("nas.lan,192.168.0.3 ssh-ed25519 {} root@nas", KEY),
+            format!("[nas.lan]:2222 ssh-ed25519 {}", OTHER_KEY),
+        ].join("\n");
+
+        assert_eq!(check(&contents, "nas.lan", 22), HostKeyStatus::Known);
+        assert_eq!(check(&contents, "192.168.0.3", 22), HostKeyStatus::Known);
+        assert_eq!(check(&contents, "router.lan", 22), HostKeyStatus::Unknown);
+        assert_eq!(
+            check(&contents, "pve.lan", 22),
+            HostKeyStatus::Changed { path: PathBuf::from("known_hosts"), line: 2 }
+        );
+        assert_eq!(
+            check(&contents, "nas.lan", 2222),
+            HostKeyStatus::Changed { path: PathBuf::from("known_hosts"), line: 4 }
+        );
+    }
+
+    #[test]
+    fn test_known_hosts_other_key_type_is_unknown() {
+        let contents = format!("nas.lan ecdsa-sha2-nistp256 {}", ECDSA_KEY);
+        assert_eq!(check(&contents, "nas.lan", 22), HostKeyStatus::Unknown);
+    }
+
+    #[test]
+    fn test_known_hosts_hashed_and_patterns() {
+        let contents = [
+            format!("|1|MDEyMzQ1Njc4OWFiY2RlZmdoaWo=|b7ylnJJwtWnGUo92yPd0Kcr8Zd8= ssh-ed25519 {}", KEY),
+            format!("*.lab,!gw.lab ssh-ed25519 {}", KEY),
+            format!("@revoked old.lab ssh-ed25519 {}", KEY),
+        ].join("\n");
+
+        assert_eq!(check(&contents, "nas.lan", 22), HostKeyStatus::Known);
+        assert_eq!(check(&contents, "pve.lab", 22), HostKeyStatus::Known);
+        assert_eq!(check(&contents, "gw.lab", 22), HostKeyStatus::Unknown);
+        assert_eq!(
+            check(&contents, "old.lab", 22),
+            HostKeyStatus::Revoked { path: PathBuf::from("known_hosts"), line: 3 }
+        );
+    }
+
+    #[test]
+    fn test_match_pattern() {
+        assert!(match_pattern("*.lan", "nas.lan"));
+        assert!(match_pattern("192.168.0.?", "192.168.0.7"));
+        assert!(match_pattern("NAS", "nas"));
+        assert!(!match_pattern("192.168.0.?", "192.168.0.17"));
+        assert!(!match_pattern("*.lan", "nas.lab"));
+    }
+}
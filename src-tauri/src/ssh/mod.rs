@@ -1,7 +1,29 @@
+mod auth;
 mod client;
+mod elevation;
+mod facts;
+mod forward;
 mod interface;
+mod known_hosts;
 mod session;
+mod shell;
+mod sftp;
+mod ssh_config;
+mod terminal;
+mod traffic;
 mod command;
-pub use session::{Host, Session, SessionInfo};
-pub use interface::Interface;
-
+pub use auth::{AuthKind, AuthRejected, KeyboardInteractivePrompt};
+pub use client::{ConnectContext, Prompter};
+pub use forward::{ForwardInfo, ForwardSpec};
+pub use known_hosts::{HostKeyError, HostKeyPrompt, KnownHosts};
+pub use session::{random_id, Host, Session, SessionInfo};
+pub use sftp::{FileInfo, Sftp, TransferProgress};
+pub use ssh_config::{ConfigHost, SshConfig};
+pub use terminal::TerminalEvent;
+pub use traffic::{
+    sample_throughput, InterfaceCounters, ReadTrafficCounters, Throughput, DEFAULT_SAMPLE_INTERVAL,
+};
+pub use interface::{Interface, ListInterfaces};
+pub use command::{
+    CommandError, CommandOutput, OutputChunk, StreamingCommandExecutor, VirtualCommand,
+};
@@ -1,22 +1,54 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Weak},
+    time::Duration,
+};
 
 use anyhow::{Context, Result};
 use rand::Rng;
-use russh::{client, ChannelMsg};
+use russh::{client, ChannelMsg, Disconnect};
 use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+    sync::{Mutex, RwLock},
+};
 
-use super::{client::Client, interface::Interface};
+use super::{
+    auth::{AuthMethod, AuthRejected},
+    client::{Client, ConnectContext},
+    command::{DetectionCache, SshCommandExecutor},
+    elevation::Elevation,
+    facts::{HostFacts, FINGERPRINT_TIMEOUT},
+    forward::{Forward, ForwardInfo, ForwardSpec, RemoteForwards},
+    interface::Interface,
+    known_hosts::HostKeyError,
+    sftp::Sftp,
+    terminal::{Terminal, TerminalEvent},
+};
 
-#[derive(Deserialize)]
+/// A keepalive goes out after this long without hearing from the server
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+/// Unanswered keepalives before the connection is dropped
+const KEEPALIVE_MAX: usize = 3;
+/// How often the monitor looks at the connection, also the first reconnect delay
+const MONITOR_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Host {
     address: String,
     port: u16,
 }
 
 impl Host {
-    fn new(address: String, port: u16) -> Self {
+    pub fn new(address: String, port: u16) -> Self {
         Self { address, port }
     }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
 }
 
 impl Into<(String, u16)> for Host {
@@ -25,22 +57,106 @@ impl Into<(String, u16)> for Host {
     }
 }
 
+/// A jump host the session is tunneled through
 #[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Hop {
+    session_id: usize,
+    user: String,
+    addrs: (String, u16),
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SessionState {
+    /// Redialing after a drop
+    Connecting,
+    Connected,
+    /// The connection dropped, waiting before the next reconnect attempt
+    Degraded,
+    /// Closed on purpose, or the server can no longer be trusted
+    Disconnected,
+    /// The stored credentials were refused while reconnecting
+    AuthFailed,
+}
+
+impl SessionState {
+    /// Whether the session is up or on its way back
+    pub fn is_active(self) -> bool {
+        matches!(self, SessionState::Connecting | SessionState::Connected | SessionState::Degraded)
+    }
+}
+
+/// Ids round-trip through JS numbers, keep them within Number.MAX_SAFE_INTEGER
+pub fn random_id() -> usize {
+    rand::thread_rng().gen_range(0..(1 << 53))
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct SessionInfo {
     id: usize,
     user: String,
     addrs: (String, u16),
+    /// Jump hosts in the order they are traversed, empty for direct connections
+    hops: Vec<Hop>,
+    /// The saved host this session was opened from, if any
+    host_id: Option<usize>,
+    state: SessionState,
+    /// Missing if the host could not be fingerprinted
+    facts: Option<HostFacts>,
 }
 
 impl SessionInfo {
-    pub fn new(user: String, addrs: (String, u16)) -> Self {
-        Self { id: rand::thread_rng().gen(), user, addrs }
+    pub fn new(user: String, addrs: (String, u16), hops: Vec<Hop>, facts: Option<HostFacts>) -> Self {
+        Self { id: random_id(), user, addrs, hops, host_id: None, state: SessionState::Connected, facts }
+    }
+}
+
+/// The connection, shared with what runs on it so the session lock is only held briefly.
+/// Swapped in place when the session reconnects.
+pub type SharedHandle = Arc<RwLock<client::Handle<Client>>>;
+
+/// What running commands need from a session, taken out of it so a long job
+/// doesn't keep the session locked
+#[derive(Clone)]
+pub struct CommandContext {
+    handle: SharedHandle,
+    detection_cache: Arc<DetectionCache>,
+    elevation: Arc<Elevation>,
+    facts: Option<HostFacts>,
+}
+
+impl CommandContext {
+    /// Runs commands on the session, remembering which implementations the host supports
+    pub fn executor(&self) -> SshCommandExecutor<'_> {
+        let executor = SshCommandExecutor::new(&self.handle)
+            .with_detection_cache(&self.detection_cache)
+            .with_elevation(&self.elevation);
+        match &self.facts {
+            Some(facts) => executor.with_host_facts(facts),
+            None => executor,
+        }
     }
 }
 
 pub struct Session {
     info: SessionInfo,
-    session: client::Handle<Client>,
+    session: SharedHandle,
+    /// Kept to redial the same way after a drop
+    auth: AuthMethod,
+    context: ConnectContext,
+    /// Keeps the jump host connection alive for as long as we tunnel through it
+    jump: Option<Arc<RwLock<Session>>>,
+    terminals: Mutex<HashMap<usize, Arc<Terminal>>>,
+    /// Opened on first use, locked on its own so the session only needs to be read
+    sftp: Mutex<Option<Arc<Sftp>>>,
+    /// Locked on their own, so no network round-trip happens under the session lock
+    forwards: Arc<Mutex<HashMap<usize, Forward>>>,
+    /// Shared with the connection handler, which receives the `-R` connections
+    remote_forwards: RemoteForwards,
+    detection_cache: Arc<DetectionCache>,
+    elevation: Arc<Elevation>,
 }
 
 impl Session {
@@ -51,26 +167,362 @@ impl Session {
     pub fn info(&self) -> &SessionInfo {
         &self.info
     }
+
+    pub fn state(&self) -> SessionState {
+        self.info.state
+    }
+
+    /// How many jump hosts the session goes through
+    pub fn depth(&self) -> usize {
+        self.info.hops.len()
+    }
+
+    /// Whether `session_id` is one of the jump hosts this session goes through
+    pub fn tunnels_through(&self, session_id: usize) -> bool {
+        self.info.hops.iter().any(|hop| hop.session_id == session_id)
+    }
+
+    pub fn host_id(&self) -> Option<usize> {
+        self.info.host_id
+    }
+
+    pub fn set_host_id(&mut self, host_id: usize) {
+        self.info.host_id = Some(host_id);
+    }
+
+    pub fn handle(&self) -> SharedHandle {
+        self.session.clone()
+    }
+
+    /// Cheap to take, the session can be unlocked as soon as it is
+    pub fn commands(&self) -> CommandContext {
+        CommandContext {
+            handle: self.session.clone(),
+            detection_cache: self.detection_cache.clone(),
+            elevation: self.elevation.clone(),
+            facts: self.info.facts.clone(),
+        }
+    }
+
+    pub async fn open_terminal<F>(&self, cols: u32, rows: u32, on_event: F) -> Result<usize>
+    where
+        F: FnMut(usize, TerminalEvent) + Send + 'static,
+    {
+        let terminal = Terminal::open(&*self.session.read().await, cols, rows, on_event).await?;
+        let id = terminal.id();
+        self.terminals.lock().await.insert(id, Arc::new(terminal));
+        Ok(id)
+    }
+
+    pub async fn terminal(&self, id: usize) -> Option<Arc<Terminal>> {
+        self.terminals.lock().await.get(&id).cloned()
+    }
+
+    /// Hangs up the shell if it is still running
+    pub async fn close_terminal(&self, id: usize) -> bool {
+        self.terminals.lock().await.remove(&id).is_some()
+    }
+
+    pub async fn sftp(&self) -> Result<Arc<Sftp>> {
+        let mut sftp = self.sftp.lock().await;
+        if let Some(sftp) = &*sftp {
+            return Ok(sftp.clone());
+        }
+        let opened = Arc::new(Sftp::open(&*self.session.read().await).await?);
+        *sftp = Some(opened.clone());
+        Ok(opened)
+    }
+
+    pub async fn forwards(&self) -> Vec<ForwardInfo> {
+        self.forwards.lock().await.values().map(Forward::info).collect()
+    }
+
+    /// Politely closes the connection and stops reconnecting, the jump host is left as is
+    pub async fn disconnect(&mut self) -> Result<()> {
+        let was_connected = self.info.state == SessionState::Connected;
+        self.info.state = SessionState::Disconnected;
+        self.terminals.get_mut().clear();
+        *self.sftp.get_mut() = None;
+        self.forwards.lock().await.clear();
+        self.remote_forwards.clear();
+        let session = self.session.read().await;
+        if !was_connected || session.is_closed() {
+            return Ok(());
+        }
+        session
+            .disconnect(Disconnect::ByApplication, "", "en")
+            .await
+            .context("Failed to disconnect")
+    }
 }
 
 impl Session {
-    pub async fn connect(addrs: (String, u16), user: String, password: String) -> Result<Session> {
-        let config = Arc::new(client::Config::default());
-        let sh = Client {};
-        let mut session = client::connect(config, &addrs, sh).await?;
-        let auth_res = session
-            .authenticate_password(user.as_str(), password)
-            .await?;
-        if !auth_res {
-            return Err(anyhow::anyhow!("authentication failed"));
-        };
+    pub async fn connect(
+        addrs: (String, u16),
+        user: String,
+        auth: AuthMethod,
+        context: ConnectContext,
+    ) -> Result<Session> {
+        let remote_forwards = RemoteForwards::default();
+        let (session, hops) =
+            Self::dial(None, &addrs, &user, &auth, context.clone(), remote_forwards.clone()).await?;
+        let session = Arc::new(RwLock::new(session));
+        let facts = Self::fingerprint(&session).await;
+        let elevation = Arc::new(Elevation::new(facts.as_ref(), auth.password()));
 
         Ok(Self {
             session,
-            info: SessionInfo::new(
-                user,
-                addrs
-            ),
+            info: SessionInfo::new(user, addrs, hops, facts),
+            auth,
+            context,
+            jump: None,
+            terminals: Mutex::default(),
+            sftp: Mutex::default(),
+            forwards: Arc::default(),
+            remote_forwards,
+            detection_cache: Arc::default(),
+            elevation,
         })
     }
+
+    /// Connects through an already established session, like `ssh -J`.
+    /// Jump sessions can themselves be tunneled, so chains can be of any depth.
+    pub async fn connect_via(
+        jump: Arc<RwLock<Session>>,
+        addrs: (String, u16),
+        user: String,
+        auth: AuthMethod,
+        context: ConnectContext,
+    ) -> Result<Session> {
+        let remote_forwards = RemoteForwards::default();
+        let (session, hops) =
+            Self::dial(Some(&jump), &addrs, &user, &auth, context.clone(), remote_forwards.clone()).await?;
+        let session = Arc::new(RwLock::new(session));
+        let facts = Self::fingerprint(&session).await;
+        let elevation = Arc::new(Elevation::new(facts.as_ref(), auth.password()));
+
+        Ok(Self {
+            session,
+            info: SessionInfo::new(user, addrs, hops, facts),
+            auth,
+            context,
+            jump: Some(jump),
+            terminals: Mutex::default(),
+            sftp: Mutex::default(),
+            forwards: Arc::default(),
+            remote_forwards,
+            detection_cache: Arc::default(),
+            elevation,
+        })
+    }
+
+    /// Starts forwarding and keeps the forward until it is stopped or the session disconnects
+    pub async fn start_forward(session: &Arc<RwLock<Session>>, spec: ForwardSpec) -> Result<ForwardInfo> {
+        let forward = match spec {
+            ForwardSpec::Remote { .. } => {
+                let (handle, remote_forwards) = {
+                    let s = session.read().await;
+                    (s.handle(), s.remote_forwards.clone())
+                };
+                let mut handle = handle.write().await;
+                Forward::request(spec, &mut handle, &remote_forwards).await?
+            }
+            _ => Forward::listen(spec, Arc::downgrade(session)).await?,
+        };
+        let info = forward.info();
+        let forwards = session.read().await.forwards.clone();
+        forwards.lock().await.insert(forward.id(), forward);
+        Ok(info)
+    }
+
+    /// Stops accepting connections for the forward, the open ones are left to finish
+    pub async fn stop_forward(session: &RwLock<Session>, id: usize) -> Result<bool> {
+        let (forward, handle, remote_forwards) = {
+            let s = session.read().await;
+            let Some(forward) = s.forwards.lock().await.remove(&id) else {
+                return Ok(false);
+            };
+            (forward, s.handle(), s.remote_forwards.clone())
+        };
+        forward.cancel(&*handle.read().await, &remote_forwards).await?;
+        Ok(true)
+    }
+
+    /// Remote forwards live on the server, a new connection has to ask for them again
+    async fn restore_forwards(
+        forwards: &Mutex<HashMap<usize, Forward>>,
+        handle: &RwLock<client::Handle<Client>>,
+    ) {
+        for forward in forwards.lock().await.values_mut() {
+            forward.restore(&mut *handle.write().await).await;
+        }
+    }
+
+    /// Watches the connection until it is closed on purpose or the session is dropped,
+    /// redialing with exponential backoff whenever it goes down.
+    /// `on_change` is called on every state transition.
+    pub async fn monitor<F>(session: Weak<RwLock<Session>>, on_change: F)
+    where
+        F: Fn(SessionInfo) + Send + 'static,
+    {
+        let mut delay = MONITOR_INTERVAL;
+        loop {
+            tokio::time::sleep(delay).await;
+            let Some(session) = session.upgrade() else {
+                return;
+            };
+
+            let (state, closed) = {
+                let s = session.read().await;
+                let closed = s.session.read().await.is_closed();
+                (s.state(), closed)
+            };
+            match state {
+                SessionState::Connected if !closed => continue,
+                SessionState::Connected => {
+                    delay = MONITOR_INTERVAL;
+                    on_change(session.write().await.set_state(SessionState::Degraded));
+                    continue;
+                }
+                SessionState::Degraded => {}
+                _ => return,
+            }
+
+            let (addrs, user, auth, context, jump, remote_forwards) = {
+                let mut s = session.write().await;
+                on_change(s.set_state(SessionState::Connecting));
+                (
+                    s.info.addrs.clone(),
+                    s.info.user.clone(),
+                    s.auth.clone(),
+                    s.context.clone(),
+                    s.jump.clone(),
+                    s.remote_forwards.clone(),
+                )
+            };
+            let result = Self::dial(jump.as_ref(), &addrs, &user, &auth, context, remote_forwards)
+                .await
+                .map(|(handle, _)| RwLock::new(handle));
+            // Done before locking, the host may not be the same machine anymore
+            let facts = match &result {
+                Ok(handle) => Self::fingerprint(handle).await,
+                Err(_) => None,
+            };
+
+            let mut s = session.write().await;
+            if s.state() != SessionState::Connecting {
+                // Disconnected while we were redialing
+                drop(s);
+                if let Ok(handle) = result {
+                    let _ = handle.into_inner().disconnect(Disconnect::ByApplication, "", "en").await;
+                }
+                return;
+            }
+            let state = match result {
+                Ok(handle) => {
+                    *s.session.write().await = handle.into_inner();
+                    s.elevation = Arc::new(Elevation::new(facts.as_ref(), s.auth.password()));
+                    s.info.facts = facts;
+                    // Channels don't survive the old connection
+                    s.terminals.get_mut().clear();
+                    *s.sftp.get_mut() = None;
+                    s.detection_cache.clear();
+                    delay = MONITOR_INTERVAL;
+                    SessionState::Connected
+                }
+                Err(e) if e.is::<AuthRejected>() => SessionState::AuthFailed,
+                Err(e) if e.is::<HostKeyError>() => SessionState::Disconnected,
+                Err(_) => {
+                    delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                    SessionState::Degraded
+                }
+            };
+            let info = s.set_state(state);
+            let (forwards, handle) = (s.forwards.clone(), s.handle());
+            drop(s);
+            if state == SessionState::Connected {
+                Self::restore_forwards(&forwards, &handle).await;
+            }
+            on_change(info);
+        }
+    }
+
+    fn set_state(&mut self, state: SessionState) -> SessionInfo {
+        self.info.state = state;
+        self.info.clone()
+    }
+
+    /// A host that can't be fingerprinted is still usable, commands fall back to probing
+    async fn fingerprint(handle: &RwLock<client::Handle<Client>>) -> Option<HostFacts> {
+        let executor = SshCommandExecutor::new(handle).with_timeout(FINGERPRINT_TIMEOUT);
+        HostFacts::gather(&executor).await.ok()
+    }
+
+    /// Opens an authenticated connection, through `jump` if there is one
+    async fn dial(
+        jump: Option<&Arc<RwLock<Session>>>,
+        addrs: &(String, u16),
+        user: &str,
+        auth: &AuthMethod,
+        context: ConnectContext,
+        remote_forwards: RemoteForwards,
+    ) -> Result<(client::Handle<Client>, Vec<Hop>)> {
+        let Some(jump) = jump else {
+            let stream = TcpStream::connect(addrs).await?;
+            let session = Self::handshake(stream, addrs, user, auth, context, remote_forwards).await?;
+            return Ok((session, Vec::new()));
+        };
+
+        let (handle, hops) = {
+            let jump = jump.read().await;
+            let mut hops = jump.info.hops.clone();
+            hops.push(Hop {
+                session_id: jump.id(),
+                user: jump.info.user.clone(),
+                addrs: jump.info.addrs.clone(),
+            });
+            (jump.handle(), hops)
+        };
+        let channel = handle
+            .read()
+            .await
+            .channel_open_direct_tcpip(addrs.0.as_str(), addrs.1 as u32, "127.0.0.1", 0)
+            .await
+            .with_context(|| {
+                let (host, port) = hops.last().map(|hop| hop.addrs.clone()).unwrap_or_default();
+                format!("{}:{} is not reachable from {}:{}", addrs.0, addrs.1, host, port)
+            })?;
+        let session =
+            Self::handshake(channel.into_stream(), addrs, user, auth, context, remote_forwards).await?;
+        Ok((session, hops))
+    }
+
+    async fn handshake<S>(
+        stream: S,
+        addrs: &(String, u16),
+        user: &str,
+        auth: &AuthMethod,
+        context: ConnectContext,
+        remote_forwards: RemoteForwards,
+    ) -> Result<client::Handle<Client>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let config = Arc::new(client::Config {
+            keepalive_interval: Some(KEEPALIVE_INTERVAL),
+            keepalive_max: KEEPALIVE_MAX,
+            ..Default::default()
+        });
+        let prompter = context.prompter.clone();
+        let sh = Client::new(addrs.0.clone(), addrs.1, context, remote_forwards);
+        let mut session = client::connect_stream(config, stream, sh).await?;
+        let auth_res = auth
+            .authenticate(&mut session, &addrs.0, user, prompter.as_ref())
+            .await?;
+        if !auth_res {
+            return Err(AuthRejected.into());
+        };
+
+        Ok(session)
+    }
 }
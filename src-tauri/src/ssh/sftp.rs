@@ -0,0 +1,309 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use russh::client::Handle;
+use russh_sftp::{
+    client::{fs::Metadata, SftpSession},
+    protocol::FileType,
+};
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use super::client::Client;
+
+const CHUNK_SIZE: usize = 256 * 1024;
+/// Files bigger than this are left to transfers, they don't belong in an editor
+const MAX_READ_SIZE: u64 = 16 * 1024 * 1024;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum FileKind {
+    Dir,
+    File,
+    Symlink,
+    Other,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FileInfo {
+    name: String,
+    path: String,
+    kind: FileKind,
+    size: u64,
+    /// Mode bits, including the file type ones
+    permissions: Option<u32>,
+    /// Seconds since the epoch
+    modified: Option<u32>,
+    user: Option<String>,
+    group: Option<String>,
+}
+
+impl FileInfo {
+    fn new(path: &str, metadata: &Metadata) -> Self {
+        let name = path.trim_end_matches('/').rsplit('/').next().unwrap_or_default();
+        Self {
+            name: name.to_string(),
+            path: path.to_string(),
+            kind: match metadata.file_type() {
+                FileType::Dir => FileKind::Dir,
+                FileType::File => FileKind::File,
+                FileType::Symlink => FileKind::Symlink,
+                FileType::Other => FileKind::Other,
+            },
+            size: metadata.len(),
+            permissions: metadata.permissions,
+            modified: metadata.mtime,
+            user: metadata.user.clone().or(metadata.uid.map(|uid| uid.to_string())),
+            group: metadata.group.clone().or(metadata.gid.map(|gid| gid.to_string())),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferProgress {
+    transferred: u64,
+    /// Unknown when the source size couldn't be read
+    total: Option<u64>,
+}
+
+/// The SFTP subsystem of a session. Every call is independent, so one `Sftp` can be
+/// shared by concurrent transfers.
+pub struct Sftp {
+    session: SftpSession,
+}
+
+impl Sftp {
+    pub async fn open(handle: &Handle<Client>) -> Result<Self> {
+        let channel = handle.channel_open_session().await?;
+        channel.request_subsystem(true, "sftp").await?;
+        let session = SftpSession::new(channel.into_stream())
+            .await
+            .context("Failed to start the SFTP subsystem")?;
+        Ok(Self { session })
+    }
+
+    /// Directories first, then by name, without `.` and `..`
+    pub async fn list_dir(&self, path: &str) -> Result<Vec<FileInfo>> {
+        let mut entries = self
+            .session
+            .read_dir(path)
+            .await
+            .with_context(|| format!("Failed to list '{}'", path))?
+            .filter(|entry| entry.file_name() != "." && entry.file_name() != "..")
+            .map(|entry| FileInfo::new(&join(path, &entry.file_name()), &entry.metadata()))
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| (a.kind != FileKind::Dir, &a.name).cmp(&(b.kind != FileKind::Dir, &b.name)));
+        Ok(entries)
+    }
+
+    pub async fn stat(&self, path: &str) -> Result<FileInfo> {
+        let metadata = self
+            .session
+            .metadata(path)
+            .await
+            .with_context(|| format!("Failed to stat '{}'", path))?;
+        Ok(FileInfo::new(path, &metadata))
+    }
+
+    /// Refuses files larger than `MAX_READ_SIZE`
+    pub async fn read_file(&self, path: &str) -> Result<Vec<u8>> {
+        let size = self.stat(path).await?.size;
+        if size > MAX_READ_SIZE {
+            anyhow::bail!("'{}' is too large to open ({} bytes, at most {})", path, size, MAX_READ_SIZE);
+        }
+        self.session
+            .read(path)
+            .await
+            .with_context(|| format!("Failed to read '{}'", path))
+    }
+
+    /// Creates the file or replaces its contents
+    pub async fn write_file(&self, path: &str, data: &[u8]) -> Result<()> {
+        let mut file = self
+            .session
+            .create(path)
+            .await
+            .with_context(|| format!("Failed to create '{}'", path))?;
+        file.write_all(data)
+            .await
+            .with_context(|| format!("Failed to write '{}'", path))?;
+        file.shutdown().await?;
+        Ok(())
+    }
+
+    pub async fn mkdir(&self, path: &str) -> Result<()> {
+        self.session
+            .create_dir(path)
+            .await
+            .with_context(|| format!("Failed to create '{}'", path))
+    }
+
+    pub async fn rename(&self, from: &str, to: &str) -> Result<()> {
+        self.session
+            .rename(from, to)
+            .await
+            .with_context(|| format!("Failed to rename '{}' to '{}'", from, to))
+    }
+
+    /// Directories have to be empty
+    pub async fn remove(&self, path: &str) -> Result<()> {
+        let result = match self.session.symlink_metadata(path).await?.file_type() {
+            FileType::Dir => self.session.remove_dir(path).await,
+            _ => self.session.remove_file(path).await,
+        };
+        result.with_context(|| format!("Failed to remove '{}'", path))
+    }
+
+    /// `on_progress` is called after every chunk, returning false aborts the transfer.
+    /// The file only appears at `local` once complete, an existing one is left alone until then.
+    pub async fn download<F>(&self, remote: &str, local: &Path, mut on_progress: F) -> Result<()>
+    where
+        F: FnMut(TransferProgress) -> bool,
+    {
+        let mut source = self
+            .session
+            .open(remote)
+            .await
+            .with_context(|| format!("Failed to open '{}'", remote))?;
+        let total = source.metadata().await.ok().and_then(|metadata| metadata.size);
+        let partial = partial_path(local);
+        let mut destination = tokio::fs::File::create(&partial)
+            .await
+            .with_context(|| format!("Failed to create '{}'", partial.display()))?;
+        let copied = copy(&mut source, &mut destination, total, &mut on_progress).await;
+        drop(destination);
+        match copied {
+            Ok(()) => tokio::fs::rename(&partial, local)
+                .await
+                .with_context(|| format!("Failed to write '{}'", local.display())),
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&partial).await;
+                Err(e).with_context(|| format!("Failed to download '{}'", remote))
+            }
+        }
+    }
+
+    /// `on_progress` is called after every chunk, returning false aborts the transfer.
+    /// Like downloads, an existing file at `remote` is only replaced once the upload is complete.
+    pub async fn upload<F>(&self, local: &Path, remote: &str, mut on_progress: F) -> Result<()>
+    where
+        F: FnMut(TransferProgress) -> bool,
+    {
+        let mut source = tokio::fs::File::open(local)
+            .await
+            .with_context(|| format!("Failed to open '{}'", local.display()))?;
+        let total = source.metadata().await.ok().map(|metadata| metadata.len());
+        let partial = format!("{}.part", remote);
+        let mut destination = self
+            .session
+            .create(partial.as_str())
+            .await
+            .with_context(|| format!("Failed to create '{}'", partial))?;
+        let copied = copy(&mut source, &mut destination, total, &mut on_progress).await;
+        drop(destination);
+        match copied {
+            Ok(()) => self.replace(&partial, remote).await,
+            Err(e) => {
+                let _ = self.session.remove_file(partial.as_str()).await;
+                Err(e).with_context(|| format!("Failed to upload '{}'", local.display()))
+            }
+        }
+    }
+
+    /// SFTP servers usually refuse to rename over an existing file, it is removed first then
+    async fn replace(&self, from: &str, to: &str) -> Result<()> {
+        if self.session.rename(from, to).await.is_ok() {
+            return Ok(());
+        }
+        if self.session.try_exists(to).await.unwrap_or_default() {
+            self.session
+                .remove_file(to)
+                .await
+                .with_context(|| format!("Failed to replace '{}'", to))?;
+        }
+        let renamed = self.rename(from, to).await;
+        if renamed.is_err() {
+            let _ = self.session.remove_file(from).await;
+        }
+        renamed
+    }
+}
+
+async fn copy<R, W>(
+    source: &mut R,
+    destination: &mut W,
+    total: Option<u64>,
+    on_progress: &mut impl FnMut(TransferProgress) -> bool,
+) -> Result<()>
+where
+    R: tokio::io::AsyncRead + Unpin,
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut transferred = 0;
+    loop {
+        let read = source.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        destination.write_all(&buffer[..read]).await?;
+        transferred += read as u64;
+        if !on_progress(TransferProgress { transferred, total }) {
+            return Err(anyhow::anyhow!("Transfer cancelled"));
+        }
+    }
+    destination.shutdown().await?;
+    Ok(())
+}
+
+/// Where a download goes until it is complete, next to its destination so renaming it is atomic.
+/// Uploads use the same `.part` suffix on the remote side.
+fn partial_path(local: &Path) -> PathBuf {
+    let mut partial = local.as_os_str().to_owned();
+    partial.push(".part");
+    partial.into()
+}
+
+fn join(dir: &str, name: &str) -> String {
+    format!("{}/{}", dir.trim_end_matches('/'), name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_copy_progress_and_cancel() {
+        let data = vec![7u8; CHUNK_SIZE * 2 + 10];
+        let mut progress = Vec::new();
+        let mut destination = Vec::new();
+        copy(&mut data.as_slice(), &mut destination, Some(data.len() as u64), &mut |p| {
+            progress.push(p.transferred);
+            true
+        })
+        .await
+        .unwrap();
+        assert_eq!(destination, data);
+        assert_eq!(progress.last(), Some(&(data.len() as u64)));
+
+        let mut destination = Vec::new();
+        let result = copy(&mut data.as_slice(), &mut destination, None, &mut |_| false).await;
+        assert!(result.is_err());
+        assert_eq!(destination.len(), CHUNK_SIZE);
+    }
+
+    #[test]
+    fn test_join() {
+        assert_eq!(join("/", "etc"), "/etc");
+        assert_eq!(join("/etc/", "hosts"), "/etc/hosts");
+        assert_eq!(join(".", "notes"), "./notes");
+    }
+
+    #[test]
+    fn test_partial_path() {
+        assert_eq!(partial_path(Path::new("/tmp/backup.tar.gz")), Path::new("/tmp/backup.tar.gz.part"));
+        assert_eq!(partial_path(Path::new("notes")), Path::new("notes.part"));
+    }
+}
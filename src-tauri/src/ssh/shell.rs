@@ -0,0 +1,85 @@
+use std::fmt::Display;
+
+/// A command line for a POSIX shell, built from a program and its arguments.
+/// Every argument is quoted so the program gets it exactly as given, whatever it contains.
+/// That doesn't stop a value starting with `-` from being taken as an option, check those first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShellCommand {
+    line: String,
+}
+
+impl ShellCommand {
+    pub fn new(program: &'static str) -> Self {
+        Self { line: quote(program) }
+    }
+
+    /// `sh -c script`. Values go in as positional parameters through `arg`, `"$1"` and on
+    /// in the script, they must never be formatted into the script itself.
+    pub fn script(script: &'static str) -> Self {
+        // The first argument after the script is $0
+        Self::new("sh").arg("-c").arg(script).arg("sh")
+    }
+
+    pub fn arg(mut self, arg: impl Display) -> Self {
+        self.line.push(' ');
+        self.line.push_str(&quote(&arg.to_string()));
+        self
+    }
+
+    pub fn args<I>(self, args: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Display,
+    {
+        args.into_iter().fold(self, Self::arg)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.line
+    }
+}
+
+impl Display for ShellCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.line)
+    }
+}
+
+/// Single quotes take everything literally, words that are safe as is are left alone to stay readable
+fn quote(value: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_-+=@%:,./".contains(c);
+    if !value.is_empty() && value.chars().all(safe) {
+        return value.to_string();
+    }
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quoting() {
+        let command = ShellCommand::new("ip").args(["-j", "addr", "show", "dev", "eth0.10"]);
+        assert_eq!(command.as_str(), "ip -j addr show dev eth0.10");
+
+        let command = ShellCommand::new("cat")
+            .arg("/srv/my files/notes.txt")
+            .arg("it's")
+            .arg("$(reboot)")
+            .arg("")
+            .arg("a\nb")
+            .arg(8080);
+        assert_eq!(
+            command.as_str(),
+            r"cat '/srv/my files/notes.txt' 'it'\''s' '$(reboot)' '' 'a
+b' 8080"
+        );
+    }
+
+    #[test]
+    fn test_script() {
+        let command = ShellCommand::script("cat \"/sys/class/net/$1/mtu\"").arg("eth0; reboot");
+        assert_eq!(command.as_str(), r#"sh -c 'cat "/sys/class/net/$1/mtu"' sh 'eth0; reboot'"#);
+    }
+}
@@ -0,0 +1,336 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use super::{auth::expand_home, known_hosts::match_pattern, session::Host};
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// A concrete host alias from an OpenSSH config with every option resolved
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigHost {
+    alias: String,
+    host: Host,
+    user: Option<String>,
+    identity_files: Vec<PathBuf>,
+    /// `[user@]host[:port]` specs, in the order they are traversed
+    proxy_jump: Vec<String>,
+}
+
+struct Directive {
+    keyword: String,
+    args: Vec<String>,
+}
+
+/// The subset of `ssh_config(5)` needed to reach a host. `Match` blocks are not
+/// evaluated, except for `Match all`.
+pub struct SshConfig {
+    directives: Vec<Directive>,
+}
+
+impl SshConfig {
+    /// Loads `~/.ssh/config`, a missing file is just an empty config
+    pub fn load_user_config() -> Result<Self> {
+        let ssh_dir = tauri::api::path::home_dir()
+            .context("Could not find the home directory")?
+            .join(".ssh");
+        Self::load(&ssh_dir.join("config"), &ssh_dir)
+    }
+
+    /// Relative `Include` paths are resolved from `include_dir`
+    pub fn load(path: &Path, include_dir: &Path) -> Result<Self> {
+        let mut directives = Vec::new();
+        read_file(path, include_dir, 0, &mut directives)?;
+        Ok(Self { directives })
+    }
+
+    /// Every alias that isn't a pattern, resolved
+    pub fn hosts(&self) -> Vec<ConfigHost> {
+        let mut aliases: Vec<&str> = Vec::new();
+        for directive in self.directives.iter().filter(|d| d.keyword == "host") {
+            for alias in &directive.args {
+                if !alias.contains(['*', '?', '!']) && !aliases.contains(&alias.as_str()) {
+                    aliases.push(alias);
+                }
+            }
+        }
+        aliases.into_iter().map(|alias| self.resolve(alias)).collect()
+    }
+
+    /// Like `ssh -G`, the first value obtained for each option wins
+    pub fn resolve(&self, alias: &str) -> ConfigHost {
+        let mut hostname: Option<&str> = None;
+        let mut user: Option<&str> = None;
+        let mut port: Option<u16> = None;
+        let mut identity_files: Vec<&str> = Vec::new();
+        let mut proxy_jump: Option<Vec<String>> = None;
+
+        let mut active = true;
+        for Directive { keyword, args } in &self.directives {
+            let Some(value) = args.first().map(String::as_str) else {
+                continue;
+            };
+            match keyword.as_str() {
+                "host" => active = match_hosts(args, alias),
+                "match" => active = value.eq_ignore_ascii_case("all"),
+                _ if !active => {}
+                "hostname" => {
+                    hostname.get_or_insert(value);
+                }
+                "user" => {
+                    user.get_or_insert(value);
+                }
+                "port" => {
+                    if port.is_none() {
+                        port = value.parse().ok();
+                    }
+                }
+                "identityfile" => identity_files.push(value),
+                "proxyjump" => {
+                    proxy_jump.get_or_insert_with(|| match value {
+                        "none" => Vec::new(),
+                        value => value.split(',').map(str::to_string).collect(),
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        let hostname = expand_tokens(hostname.unwrap_or(alias), alias, alias, user, port);
+        let identity_files = identity_files
+            .into_iter()
+            .map(|path| expand_tokens(path, alias, &hostname, user, port))
+            .map(|path| expand_home(Path::new(&path)))
+            .collect();
+
+        ConfigHost {
+            alias: alias.to_string(),
+            host: Host::new(hostname, port.unwrap_or(22)),
+            user: user.map(str::to_string),
+            identity_files,
+            proxy_jump: proxy_jump.unwrap_or_default(),
+        }
+    }
+}
+
+fn read_file(path: &Path, include_dir: &Path, depth: usize, directives: &mut Vec<Directive>) -> Result<()> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read '{}'", path.display())),
+    };
+    parse_into(&contents, include_dir, depth, directives)
+        .with_context(|| format!("Invalid ssh config '{}'", path.display()))
+}
+
+fn parse_into(contents: &str, include_dir: &Path, depth: usize, directives: &mut Vec<Directive>) -> Result<()> {
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (keyword, rest) = line
+            .split_once(|c: char| c.is_whitespace() || c == '=')
+            .unwrap_or((line, ""));
+        let rest = rest.trim_start();
+        let rest = rest.strip_prefix('=').unwrap_or(rest);
+        let directive = Directive {
+            keyword: keyword.to_ascii_lowercase(),
+            args: split_args(rest)?,
+        };
+
+        if directive.keyword != "include" {
+            directives.push(directive);
+            continue;
+        }
+        if depth >= MAX_INCLUDE_DEPTH {
+            return Err(anyhow::anyhow!("Too many nested Include directives"));
+        }
+        for pattern in &directive.args {
+            for path in glob(&include_dir.join(expand_home(Path::new(pattern))))? {
+                read_file(&path, include_dir, depth + 1, directives)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn split_args(value: &str) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quoted = false;
+    for c in value.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_arg = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if quoted {
+        return Err(anyhow::anyhow!("Unterminated quote in '{}'", value));
+    }
+    if in_arg {
+        args.push(current);
+    }
+    Ok(args)
+}
+
+/// Wildcards are only supported in the file name, which covers the usual `config.d/*`
+fn glob(path: &Path) -> Result<Vec<PathBuf>> {
+    let file_name = path.file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_default();
+    if !file_name.contains(['*', '?']) {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let Some(dir) = path.parent() else {
+        return Ok(Vec::new());
+    };
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to list '{}'", dir.display())),
+    };
+    let mut paths = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| match_pattern(&file_name, &entry.file_name().to_string_lossy()))
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    paths.sort();
+    Ok(paths)
+}
+
+fn match_hosts(patterns: &[String], alias: &str) -> bool {
+    let mut matched = false;
+    for pattern in patterns {
+        if let Some(negated) = pattern.strip_prefix('!') {
+            if match_pattern(negated, alias) {
+                return false;
+            }
+        } else {
+            matched |= match_pattern(pattern, alias);
+        }
+    }
+    matched
+}
+
+fn expand_tokens(value: &str, alias: &str, hostname: &str, user: Option<&str>, port: Option<u16>) -> String {
+    let mut expanded = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => expanded.push('%'),
+            Some('h') => expanded.push_str(hostname),
+            Some('n') => expanded.push_str(alias),
+            Some('p') => expanded.push_str(&port.unwrap_or(22).to_string()),
+            Some('r') => expanded.push_str(user.unwrap_or_default()),
+            Some('d') => {
+                if let Some(home) = tauri::api::path::home_dir() {
+                    expanded.push_str(&home.to_string_lossy());
+                }
+            }
+            Some(other) => {
+                expanded.push('%');
+                expanded.push(other);
+            }
+            None => expanded.push('%'),
+        }
+    }
+    expanded
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    impl SshConfig {
+        fn parse(contents: &str, include_dir: &Path) -> Result<Self> {
+            let mut directives = Vec::new();
+            parse_into(contents, include_dir, 0, &mut directives)?;
+            Ok(Self { directives })
+        }
+    }
+
+    #[test]
+    fn test_ssh_config_resolve() {
+        let config = [
+            "# Homelab",
+            "Host pve pve.lan",
+            "    HostName 192.168.0.2",
+            "    User root",
+            "",
+            "Host nas",
+            "    HostName=%h.lan",
+            "    Port 2222",
+            "    IdentityFile \"/keys/%r@%h\"",
+            "    ProxyJump admin@bastion:2200,gw",
+            "",
+            "Host *.lan !pve.lan",
+            "    User admin",
+            "",
+            "Host *",
+            "    User fallback",
+            "    IdentityFile /keys/default",
+        ].join("\n");
+        let config = SshConfig::parse(&config, Path::new("/unused")).unwrap();
+
+        let hosts = config.hosts();
+        assert_eq!(hosts.iter().map(|h| h.alias.as_str()).collect::<Vec<_>>(), ["pve", "pve.lan", "nas"]);
+
+        assert_eq!(hosts[0].host, Host::new("192.168.0.2".to_string(), 22));
+        assert_eq!(hosts[0].user.as_deref(), Some("root"));
+        assert_eq!(hosts[0].identity_files, [PathBuf::from("/keys/default")]);
+        assert!(hosts[0].proxy_jump.is_empty());
+
+        assert_eq!(hosts[2].host, Host::new("nas.lan".to_string(), 2222));
+        assert_eq!(hosts[2].user.as_deref(), Some("fallback"));
+        assert_eq!(
+            hosts[2].identity_files,
+            [PathBuf::from("/keys/fallback@nas.lan"), PathBuf::from("/keys/default")]
+        );
+        assert_eq!(hosts[2].proxy_jump, ["admin@bastion:2200", "gw"]);
+
+        assert_eq!(config.resolve("router.lan").user.as_deref(), Some("admin"));
+        assert_eq!(config.resolve("pve.lan").user.as_deref(), Some("root"));
+    }
+
+    #[test]
+    fn test_ssh_config_include() {
+        let dir = std::env::temp_dir().join(format!("lazylab-ssh-config-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("config.d")).unwrap();
+        std::fs::write(dir.join("config.d/10-lab"), "Host lab\n  HostName 10.0.0.1\n").unwrap();
+        std::fs::write(dir.join("config.d/20-vpn"), "Host vpn\n  HostName 10.8.0.1\n  Port 2022\n").unwrap();
+        std::fs::write(dir.join("config"), "Include config.d/*\n\nHost *\n  User me\n").unwrap();
+
+        let config = SshConfig::load(&dir.join("config"), &dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let hosts = config.unwrap().hosts();
+
+        assert_eq!(hosts.len(), 2);
+        assert_eq!(hosts[0].alias, "lab");
+        assert_eq!(hosts[0].user.as_deref(), Some("me"));
+        assert_eq!(hosts[1].host, Host::new("10.8.0.1".to_string(), 2022));
+    }
+
+    #[test]
+    fn test_ssh_config_unterminated_quote() {
+        assert!(SshConfig::parse("IdentityFile \"/keys/id", Path::new("/unused")).is_err());
+    }
+}
@@ -0,0 +1,179 @@
+use anyhow::Result;
+use russh::{
+    client::{self, Handle},
+    Channel, ChannelMsg,
+};
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use super::{client::Client, session::random_id};
+
+const TERM: &str = "xterm-256color";
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum TerminalEvent {
+    Output { data: String },
+    /// The shell is gone, `exit_status` is missing if the connection dropped
+    #[serde(rename_all = "camelCase")]
+    Exited { exit_status: Option<u32> },
+}
+
+enum Control {
+    Input(Vec<u8>),
+    Resize { cols: u32, rows: u32 },
+    Close,
+}
+
+/// An interactive shell on a PTY. The channel is driven by its own task,
+/// this is only a handle to it.
+pub struct Terminal {
+    id: usize,
+    control: mpsc::UnboundedSender<Control>,
+}
+
+impl Terminal {
+    /// `on_event` gets the terminal id along with each event
+    pub async fn open<F>(handle: &Handle<Client>, cols: u32, rows: u32, mut on_event: F) -> Result<Self>
+    where
+        F: FnMut(usize, TerminalEvent) + Send + 'static,
+    {
+        let channel = handle.channel_open_session().await?;
+        channel.request_pty(true, TERM, cols, rows, 0, 0, &[]).await?;
+        channel.request_shell(true).await?;
+
+        let id = random_id();
+        let (control, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(drive(channel, receiver, move |event| on_event(id, event)));
+        Ok(Self { id, control })
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn write(&self, data: Vec<u8>) -> Result<()> {
+        self.send(Control::Input(data))
+    }
+
+    pub fn resize(&self, cols: u32, rows: u32) -> Result<()> {
+        self.send(Control::Resize { cols, rows })
+    }
+
+    pub fn close(&self) {
+        let _ = self.send(Control::Close);
+    }
+
+    fn send(&self, control: Control) -> Result<()> {
+        self.control
+            .send(control)
+            .map_err(|_| anyhow::anyhow!("Terminal {} has exited", self.id))
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+async fn drive<F>(
+    mut channel: Channel<client::Msg>,
+    mut control: mpsc::UnboundedReceiver<Control>,
+    mut on_event: F,
+) where
+    F: FnMut(TerminalEvent) + Send,
+{
+    let mut decoder = Utf8Decoder::default();
+    let mut exit_status = None;
+    loop {
+        tokio::select! {
+            msg = channel.wait() => match msg {
+                // The PTY merges stderr into stdout, extended data is only there for odd servers
+                Some(ChannelMsg::Data { data }) | Some(ChannelMsg::ExtendedData { data, .. }) => {
+                    on_event(TerminalEvent::Output { data: decoder.decode(&data) })
+                }
+                Some(ChannelMsg::ExitStatus { exit_status: status }) => exit_status = Some(status),
+                Some(_) => {}
+                None => break,
+            },
+            control = control.recv() => {
+                let sent = match control {
+                    Some(Control::Input(data)) => channel.data(data.as_slice()).await,
+                    Some(Control::Resize { cols, rows }) => {
+                        channel.window_change(cols, rows, 0, 0).await
+                    }
+                    Some(Control::Close) | None => {
+                        let _ = channel.close().await;
+                        break;
+                    }
+                };
+                if sent.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    on_event(TerminalEvent::Exited { exit_status });
+}
+
+/// Multi-byte characters can be split across packets, the incomplete tail waits for the next one
+#[derive(Default)]
+pub(super) struct Utf8Decoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    pub fn decode(&mut self, data: &[u8]) -> String {
+        self.pending.extend_from_slice(data);
+        // Invalid sequences are replaced, only a truncated one at the very end is kept
+        let mut complete = 0;
+        let complete = loop {
+            match std::str::from_utf8(&self.pending[complete..]) {
+                Ok(_) => break self.pending.len(),
+                Err(e) => match e.error_len() {
+                    Some(invalid) => complete += e.valid_up_to() + invalid,
+                    None => break complete + e.valid_up_to(),
+                },
+            }
+        };
+        let rest = self.pending.split_off(complete);
+        let decoded = String::from_utf8_lossy(&self.pending).into_owned();
+        self.pending = rest;
+        decoded
+    }
+
+    /// For when no more data is coming, a truncated character left over is replaced
+    pub fn finish(&mut self) -> String {
+        let decoded = String::from_utf8_lossy(&self.pending).into_owned();
+        self.pending.clear();
+        decoded
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_utf8_decoder_split_character() {
+        let mut decoder = Utf8Decoder::default();
+        let bytes = "é→".as_bytes();
+        assert_eq!(decoder.decode(&bytes[..1]), "");
+        assert_eq!(decoder.decode(&bytes[1..3]), "é");
+        assert_eq!(decoder.decode(&bytes[3..]), "→");
+        assert_eq!(decoder.decode(b"\xffok"), "\u{fffd}ok");
+        assert_eq!(decoder.decode(&bytes[..1]), "");
+        assert_eq!(decoder.finish(), "\u{fffd}");
+        assert_eq!(decoder.finish(), "");
+    }
+
+    #[test]
+    fn test_utf8_decoder_invalid_then_split_character() {
+        let mut decoder = Utf8Decoder::default();
+        let bytes = "é".as_bytes();
+        assert_eq!(decoder.decode(&[b'a', 0xff, b'b', bytes[0]]), "a\u{fffd}b");
+        assert_eq!(decoder.decode(&bytes[1..]), "é");
+        assert_eq!(decoder.finish(), "");
+    }
+}
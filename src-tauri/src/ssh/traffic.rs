@@ -0,0 +1,346 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{watch, RwLock},
+    time::MissedTickBehavior,
+};
+
+use super::{
+    command::{CommandError, CommandOutput, ConcreteCommand, VirtualCommand},
+    facts::HostFacts,
+    session::{Session, SessionState},
+    shell::ShellCommand,
+};
+
+pub const DEFAULT_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+/// Each sample is a remote command, more often than this would mostly measure the round-trip
+pub const MIN_SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Totals since the interface came up
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Counters {
+    bytes: u64,
+    packets: u64,
+    errors: u64,
+    drops: u64,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct InterfaceCounters {
+    name: String,
+    rx: Counters,
+    tx: Counters,
+}
+
+/// Per second, over the time between two readings
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Rates {
+    bytes: f64,
+    packets: f64,
+    errors: f64,
+    drops: f64,
+}
+
+impl Rates {
+    /// A counter that went down was reset, like when a driver reloads, and counts as no traffic
+    fn between(previous: &Counters, current: &Counters, seconds: f64) -> Self {
+        let rate = |previous: u64, current: u64| current.saturating_sub(previous) as f64 / seconds;
+        Self {
+            bytes: rate(previous.bytes, current.bytes),
+            packets: rate(previous.packets, current.packets),
+            errors: rate(previous.errors, current.errors),
+            drops: rate(previous.drops, current.drops),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Throughput {
+    name: String,
+    rx: Rates,
+    tx: Rates,
+    /// As of the reading the rates end at
+    rx_totals: Counters,
+    tx_totals: Counters,
+}
+
+/// Turns successive counter readings into rates
+#[derive(Default)]
+pub struct ThroughputSampler {
+    previous: Option<(Instant, HashMap<String, InterfaceCounters>)>,
+}
+
+impl ThroughputSampler {
+    /// `None` for the first reading, there is nothing to compare it to yet.
+    /// Interfaces that just appeared are left out until the next one.
+    pub fn add(&mut self, at: Instant, counters: Vec<InterfaceCounters>) -> Option<Vec<Throughput>> {
+        let current: HashMap<_, _> = counters.into_iter().map(|c| (c.name.clone(), c)).collect();
+        let (previous_at, previous) = self.previous.replace((at, current))?;
+        let (_, current) = self.previous.as_ref()?;
+        let seconds = at.checked_duration_since(previous_at)?.as_secs_f64();
+        if seconds == 0.0 {
+            return None;
+        }
+
+        let mut rates: Vec<_> = current.values()
+            .filter_map(|counters| {
+                let before = previous.get(&counters.name)?;
+                Some(Throughput {
+                    name: counters.name.clone(),
+                    rx: Rates::between(&before.rx, &counters.rx, seconds),
+                    tx: Rates::between(&before.tx, &counters.tx, seconds),
+                    rx_totals: counters.rx,
+                    tx_totals: counters.tx,
+                })
+            })
+            .collect();
+        rates.sort_by(|a, b| a.name.cmp(&b.name));
+        Some(rates)
+    }
+
+    /// Forgets the last reading, for when there was a gap
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+}
+
+/// Reads the counters every `interval` and hands the rates to `on_sample` until `cancel` fires.
+/// Readings that fail while the session is reconnecting are skipped, other failures end it.
+pub async fn sample_throughput(
+    session: &RwLock<Session>,
+    interval: Duration,
+    mut cancel: watch::Receiver<bool>,
+    mut on_sample: impl FnMut(Vec<Throughput>),
+) -> Result<()> {
+    let mut sampler = ThroughputSampler::default();
+    let mut ticks = tokio::time::interval(interval.max(MIN_SAMPLE_INTERVAL));
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {}
+            _ = cancel.changed() => return Ok(()),
+        }
+        let reading = {
+            let commands = session.read().await.commands();
+            let executor = commands.executor().with_cancel(cancel.clone());
+            ReadTrafficCounters.execute(&executor).await
+        };
+        match reading {
+            Ok(counters) => {
+                if let Some(rates) = sampler.add(Instant::now(), counters) {
+                    on_sample(rates);
+                }
+            }
+            Err(e) if matches!(e.downcast_ref(), Some(CommandError::Cancelled { .. })) => return Ok(()),
+            Err(_) if matches!(
+                session.read().await.state(),
+                SessionState::Connecting | SessionState::Degraded
+            ) => sampler.reset(),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+pub struct ReadTrafficCounters;
+
+impl ReadTrafficCounters {
+    const IMPLEMENTATIONS: [&'static dyn ConcreteCommand<Vec<InterfaceCounters>>; 2] =
+        [&ReadTrafficCountersProc {}, &ReadTrafficCountersIp {}];
+}
+
+impl VirtualCommand<Vec<InterfaceCounters>, 2> for ReadTrafficCounters {
+    fn implementations(&self) -> [&'static dyn ConcreteCommand<Vec<InterfaceCounters>>; 2] {
+        Self::IMPLEMENTATIONS
+    }
+}
+
+/// `/proc/net/dev`, one file read and no tools needed
+struct ReadTrafficCountersProc;
+
+impl ConcreteCommand<Vec<InterfaceCounters>> for ReadTrafficCountersProc {
+    fn detection_command(&self) -> ShellCommand {
+        ShellCommand::new("head").args(["-n", "2", "/proc/net/dev"])
+    }
+
+    fn execution_command(&self) -> ShellCommand {
+        ShellCommand::new("cat").arg("/proc/net/dev")
+    }
+
+    fn parse_detection_output(&self, output: &CommandOutput) -> Result<bool> {
+        Ok(output.success() && output.stdout.contains("Receive"))
+    }
+
+    fn supports(&self, facts: &HostFacts) -> Option<bool> {
+        (!facts.is_linux()).then_some(false)
+    }
+
+    /// Two header lines, then `eth0: 1234 5 0 0 ...` with eight receive then eight transmit
+    /// columns. Old kernels leave no space after the colon once the numbers get long.
+    fn parse_execution_output(&self, output: &str) -> Result<Vec<InterfaceCounters>> {
+        output.lines().skip(2).filter(|line| !line.trim().is_empty()).map(|line| {
+            let (name, columns) = line.split_once(':')
+                .ok_or(anyhow::anyhow!("No interface name in '{}'", line))?;
+            let columns = columns.split_whitespace()
+                .map(str::parse)
+                .collect::<Result<Vec<u64>, _>>()
+                .map_err(|e| anyhow::anyhow!("Invalid counter in '{}': {}", line, e))?;
+            let [rx_bytes, rx_packets, rx_errors, rx_drops, _, _, _, _, tx_bytes, tx_packets, tx_errors, tx_drops, ..] =
+                columns[..]
+            else {
+                return Err(anyhow::anyhow!("Missing counters in '{}'", line));
+            };
+            Ok(InterfaceCounters {
+                name: name.trim().to_string(),
+                rx: Counters { bytes: rx_bytes, packets: rx_packets, errors: rx_errors, drops: rx_drops },
+                tx: Counters { bytes: tx_bytes, packets: tx_packets, errors: tx_errors, drops: tx_drops },
+            })
+        }).collect()
+    }
+}
+
+/// `ip -j -s link`, for when `/proc` isn't readable
+struct ReadTrafficCountersIp;
+
+impl ConcreteCommand<Vec<InterfaceCounters>> for ReadTrafficCountersIp {
+    fn detection_command(&self) -> ShellCommand {
+        ShellCommand::new("ip").args(["-j", "-s", "link", "show", "lo"])
+    }
+
+    fn execution_command(&self) -> ShellCommand {
+        ShellCommand::new("ip").args(["-j", "-s", "link"])
+    }
+
+    fn parse_detection_output(&self, output: &CommandOutput) -> Result<bool> {
+        Ok(output.success() && output.stdout.trim_start().starts_with('['))
+    }
+
+    fn supports(&self, facts: &HostFacts) -> Option<bool> {
+        (!facts.is_linux()).then_some(false)
+    }
+
+    fn parse_execution_output(&self, output: &str) -> Result<Vec<InterfaceCounters>> {
+        let links: Vec<IpLinkStats> = serde_json::from_str(output)
+            .map_err(|e| anyhow::anyhow!("Invalid ip JSON output: {}", e))?;
+        Ok(links.into_iter().map(|link| InterfaceCounters {
+            name: link.ifname,
+            rx: link.stats64.rx.into(),
+            tx: link.stats64.tx.into(),
+        }).collect())
+    }
+}
+
+#[derive(Deserialize)]
+struct IpLinkStats {
+    ifname: String,
+    stats64: IpStats64,
+}
+
+#[derive(Deserialize)]
+struct IpStats64 {
+    rx: IpStats,
+    tx: IpStats,
+}
+
+#[derive(Deserialize)]
+struct IpStats {
+    bytes: u64,
+    packets: u64,
+    errors: u64,
+    dropped: u64,
+}
+
+impl From<IpStats> for Counters {
+    fn from(stats: IpStats) -> Self {
+        Self {
+            bytes: stats.bytes,
+            packets: stats.packets,
+            errors: stats.errors,
+            drops: stats.dropped,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::ssh::command::test::MockCommandExecutor;
+
+    use super::*;
+
+    fn counters(name: &str, rx_bytes: u64, tx_bytes: u64) -> InterfaceCounters {
+        InterfaceCounters {
+            name: name.to_string(),
+            rx: Counters { bytes: rx_bytes, packets: rx_bytes / 100, ..Default::default() },
+            tx: Counters { bytes: tx_bytes, packets: tx_bytes / 100, ..Default::default() },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_traffic_counters() {
+        let proc_net_dev = [
+            "Inter-|   Receive                                                |  Transmit",
+            " face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed",
+            "    lo:   31468     402    0    0    0     0          0         0    31468     402    0    0    0     0       0          0",
+            "  eth0:12345678901 9270114 3 17    0     0          0         0 987654321 4521337    0    2    0     0       0          0",
+        ].join("\n");
+        let executor = MockCommandExecutor::new(HashMap::from([
+            ("head -n 2 /proc/net/dev".to_string(), proc_net_dev.lines().take(2).collect::<Vec<_>>().join("\n")),
+            ("cat /proc/net/dev".to_string(), proc_net_dev.clone()),
+        ]));
+        let interfaces = ReadTrafficCounters.execute(&executor).await.unwrap();
+        assert_eq!(interfaces.len(), 2);
+        assert_eq!(interfaces[0].name, "lo");
+        assert_eq!(interfaces[0].rx.packets, 402);
+        assert_eq!(interfaces[1].name, "eth0");
+        assert_eq!(interfaces[1].rx, Counters { bytes: 12345678901, packets: 9270114, errors: 3, drops: 17 });
+        assert_eq!(interfaces[1].tx, Counters { bytes: 987654321, packets: 4521337, errors: 0, drops: 2 });
+
+        let ip = r#"[{"ifindex":2,"ifname":"eth0","flags":["UP"],"mtu":1500,"operstate":"UP",
+            "stats64":{"rx":{"bytes":5000,"packets":50,"errors":1,"dropped":2,"over_errors":0,"multicast":0},
+                       "tx":{"bytes":7000,"packets":70,"errors":0,"dropped":3,"carrier_errors":0,"collisions":0}}}]"#;
+        let interfaces = ReadTrafficCountersIp.parse_execution_output(ip).unwrap();
+        assert_eq!(interfaces, [InterfaceCounters {
+            name: "eth0".to_string(),
+            rx: Counters { bytes: 5000, packets: 50, errors: 1, drops: 2 },
+            tx: Counters { bytes: 7000, packets: 70, errors: 0, drops: 3 },
+        }]);
+    }
+
+    #[test]
+    fn test_throughput_sampler() {
+        let mut sampler = ThroughputSampler::default();
+        let start = Instant::now();
+        assert_eq!(sampler.add(start, vec![counters("eth0", 1_000, 500)]), None);
+
+        let rates = sampler
+            .add(start + Duration::from_secs(2), vec![counters("eth0", 201_000, 4_500), counters("wg0", 10, 10)])
+            .unwrap();
+        // wg0 has no earlier reading to compare to
+        assert_eq!(rates.len(), 1);
+        assert_eq!(rates[0].rx.bytes, 100_000.0);
+        assert_eq!(rates[0].rx.packets, 1_000.0);
+        assert_eq!(rates[0].tx.bytes, 2_000.0);
+        assert_eq!(rates[0].rx_totals, counters("eth0", 201_000, 4_500).rx);
+
+        // eth0 was reset
+        let rates = sampler
+            .add(start + Duration::from_secs(3), vec![counters("eth0", 300, 300), counters("wg0", 1_010, 10)])
+            .unwrap();
+        assert_eq!(rates[0].name, "eth0");
+        assert_eq!(rates[0].rx.bytes, 0.0);
+        assert_eq!(rates[1].rx.bytes, 1_000.0);
+
+        sampler.reset();
+        assert_eq!(sampler.add(start + Duration::from_secs(4), vec![counters("eth0", 400, 400)]), None);
+    }
+}
@@ -0,0 +1,251 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use aes_gcm::{aead::Aead, Aes256Gcm, KeyInit, Nonce};
+use anyhow::{Context, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use data_encoding::BASE64;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+const VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug)]
+pub enum VaultError {
+    Locked,
+    WrongPassword,
+    EntryNotFound(String),
+}
+
+impl std::fmt::Display for VaultError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            VaultError::Locked => write!(f, "The vault is locked"),
+            VaultError::WrongPassword => write!(f, "Wrong master password"),
+            VaultError::EntryNotFound(entry) => write!(f, "No vault entry named '{}'", entry),
+        }
+    }
+}
+
+impl std::error::Error for VaultError {}
+
+#[derive(Serialize, Deserialize)]
+struct KdfParams {
+    memory: u32,
+    iterations: u32,
+    parallelism: u32,
+    salt: String,
+}
+
+/// On disk, everything but the key derivation parameters is a single AES-256-GCM blob
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    kdf: KdfParams,
+    nonce: String,
+    ciphertext: String,
+}
+
+struct Unlocked {
+    key: Zeroizing<[u8; 32]>,
+    salt: Vec<u8>,
+    entries: BTreeMap<String, Zeroizing<String>>,
+}
+
+/// Passwords and key passphrases, encrypted with a key derived from a master password
+/// (Argon2id). It stays unlocked for the lifetime of the app unless locked explicitly.
+pub struct Vault {
+    path: PathBuf,
+    params: Params,
+    unlocked: Option<Unlocked>,
+}
+
+impl Vault {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, params: Params::default(), unlocked: None }
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        self.unlocked.is_some()
+    }
+
+    /// Creates an empty vault protected by `master_password` if there is none yet
+    pub async fn unlock(&mut self, master_password: &str) -> Result<()> {
+        let contents = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let mut salt = vec![0; SALT_LEN];
+                rand::thread_rng().fill_bytes(&mut salt);
+                let key = derive_key(&self.params, master_password, &salt).await?;
+                self.unlocked = Some(Unlocked { key, salt, entries: BTreeMap::new() });
+                return self.write();
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to read '{}'", self.path.display())),
+        };
+
+        let file: VaultFile = serde_json::from_str(&contents)
+            .with_context(|| format!("Invalid vault '{}'", self.path.display()))?;
+        if file.version != VERSION {
+            return Err(anyhow::anyhow!("Unsupported vault version {}", file.version));
+        }
+        let params = Params::new(file.kdf.memory, file.kdf.iterations, file.kdf.parallelism, Some(32))
+            .map_err(|e| anyhow::anyhow!("Invalid vault key derivation parameters: {}", e))?;
+        let salt = BASE64.decode(file.kdf.salt.as_bytes())?;
+        let nonce = BASE64.decode(file.nonce.as_bytes())?;
+        let ciphertext = BASE64.decode(file.ciphertext.as_bytes())?;
+        if nonce.len() != NONCE_LEN {
+            return Err(anyhow::anyhow!("Invalid vault nonce"));
+        }
+
+        let key = derive_key(&params, master_password, &salt).await?;
+        let plaintext = Zeroizing::new(
+            Aes256Gcm::new(key.as_ref().into())
+                .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+                .map_err(|_| VaultError::WrongPassword)?,
+        );
+        let entries: BTreeMap<String, String> = serde_json::from_slice(&plaintext)?;
+
+        self.params = params;
+        self.unlocked = Some(Unlocked {
+            key,
+            salt,
+            entries: entries.into_iter().map(|(k, v)| (k, Zeroizing::new(v))).collect(),
+        });
+        Ok(())
+    }
+
+    pub fn lock(&mut self) {
+        self.unlocked = None;
+    }
+
+    pub fn entries(&self) -> Result<Vec<String>> {
+        Ok(self.unlocked()?.entries.keys().cloned().collect())
+    }
+
+    pub fn get(&self, entry: &str) -> Result<String> {
+        self.unlocked()?
+            .entries
+            .get(entry)
+            .map(|secret| secret.to_string())
+            .ok_or(VaultError::EntryNotFound(entry.to_string()).into())
+    }
+
+    pub fn set(&mut self, entry: String, secret: String) -> Result<()> {
+        self.unlocked_mut()?.entries.insert(entry, Zeroizing::new(secret));
+        self.write()
+    }
+
+    pub fn remove(&mut self, entry: &str) -> Result<()> {
+        self.unlocked_mut()?
+            .entries
+            .remove(entry)
+            .ok_or(VaultError::EntryNotFound(entry.to_string()))?;
+        self.write()
+    }
+
+    fn unlocked(&self) -> Result<&Unlocked> {
+        Ok(self.unlocked.as_ref().ok_or(VaultError::Locked)?)
+    }
+
+    fn unlocked_mut(&mut self) -> Result<&mut Unlocked> {
+        Ok(self.unlocked.as_mut().ok_or(VaultError::Locked)?)
+    }
+
+    fn write(&self) -> Result<()> {
+        let unlocked = self.unlocked()?;
+        let entries: BTreeMap<&str, &str> = unlocked
+            .entries
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let plaintext = Zeroizing::new(serde_json::to_vec(&entries)?);
+
+        // A nonce must never be reused with the same key, so every write gets a fresh one
+        let mut nonce = [0; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = Aes256Gcm::new(unlocked.key.as_ref().into())
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_ref())
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt the vault"))?;
+
+        let file = VaultFile {
+            version: VERSION,
+            kdf: KdfParams {
+                memory: self.params.m_cost(),
+                iterations: self.params.t_cost(),
+                parallelism: self.params.p_cost(),
+                salt: BASE64.encode(&unlocked.salt),
+            },
+            nonce: BASE64.encode(&nonce),
+            ciphertext: BASE64.encode(&ciphertext),
+        };
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(&file)?)
+            .and_then(|_| std::fs::rename(&tmp, &self.path))
+            .with_context(|| format!("Failed to write '{}'", self.path.display()))
+    }
+}
+
+/// Argon2id takes a good fraction of a second on purpose, it gets a blocking thread
+async fn derive_key(params: &Params, master_password: &str, salt: &[u8]) -> Result<Zeroizing<[u8; 32]>> {
+    let master_password = Zeroizing::new(master_password.to_string());
+    let (params, salt) = (params.clone(), salt.to_vec());
+    tokio::task::spawn_blocking(move || {
+        let mut key = Zeroizing::new([0; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(master_password.as_bytes(), &salt, key.as_mut())
+            .map_err(|e| anyhow::anyhow!("Key derivation failed: {}", e))?;
+        Ok(key)
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn vault(path: &std::path::Path) -> Vault {
+        // Keep the tests fast, the real parameters are Argon2's defaults
+        Vault { params: Params::new(64, 1, 1, Some(32)).unwrap(), ..Vault::new(path.to_path_buf()) }
+    }
+
+    #[tokio::test]
+    async fn test_vault_round_trip() {
+        let path = std::env::temp_dir().join(format!("lazylab-vault-{}.json", std::process::id()));
+        let mut first = vault(&path);
+        assert!(!first.exists());
+        assert!(matches!(
+            first.get("nas").unwrap_err().downcast_ref::<VaultError>(),
+            Some(VaultError::Locked)
+        ));
+
+        first.unlock("hunter2").await.unwrap();
+        first.set("nas".to_string(), "s3cret".to_string()).unwrap();
+        first.set("pve".to_string(), "passphrase".to_string()).unwrap();
+        first.remove("pve").unwrap();
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("s3cret"));
+
+        let mut second = vault(&path);
+        let err = second.unlock("hunter3").await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<VaultError>(), Some(VaultError::WrongPassword)));
+        assert!(!second.is_unlocked());
+
+        second.unlock("hunter2").await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(second.entries().unwrap(), ["nas"]);
+        assert_eq!(second.get("nas").unwrap(), "s3cret");
+        assert!(matches!(
+            second.get("pve").unwrap_err().downcast_ref::<VaultError>(),
+            Some(VaultError::EntryNotFound(_))
+        ));
+    }
+}
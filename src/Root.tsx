@@ -1,13 +1,31 @@
 import {CssBaseline, ThemeProvider, Grid} from "@mui/material";
 import {Outlet} from "react-router-dom";
+import {useRef} from "react";
 import theme from "./theme.ts";
 import AppBar from "./components/AppBar.tsx";
+import HostKeyPromptDialog from "./components/HostKeyPromptDialog.tsx";
+import KeyboardInteractivePromptDialog from "./components/KeyboardInteractivePromptDialog.tsx";
+import VaultUnlockDialog from "./components/VaultUnlockDialog.tsx";
+import AutoConnectErrors from "./components/AutoConnectErrors.tsx";
+import {frontendReady} from "./types.ts";
 
 interface RootProps {
 
 }
 
+/** The dialogs below that must be listening before the backend starts auto-connecting */
+const LISTENERS = ["hostKey", "keyboardInteractive", "autoConnectErrors"];
+
 const Root = ({}: RootProps) => {
+    const listening = useRef(new Set<string>());
+    // Registering a listener is asynchronous, events sent before it resolves are lost
+    const onListening = (listener: string) => () => {
+        listening.current.add(listener);
+        if (listening.current.size === LISTENERS.length) {
+            frontendReady();
+        }
+    };
+
     return (
         <ThemeProvider theme={theme}>
             <CssBaseline>
@@ -17,6 +35,10 @@ const Root = ({}: RootProps) => {
                         <Outlet/>
                     </Grid>
                 </Grid>
+                <HostKeyPromptDialog onListening={onListening("hostKey")}/>
+                <KeyboardInteractivePromptDialog onListening={onListening("keyboardInteractive")}/>
+                <VaultUnlockDialog/>
+                <AutoConnectErrors onListening={onListening("autoConnectErrors")}/>
             </CssBaseline>
         </ThemeProvider>
     );
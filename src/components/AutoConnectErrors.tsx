@@ -0,0 +1,38 @@
+import {Alert, Snackbar} from "@mui/material";
+import {useEffect, useState} from "react";
+import {AutoConnectFailed, getHosts, HostDefinition, onAutoConnectFailed} from "../types.ts";
+
+interface AutoConnectErrorsProps {
+    /** Called once the event listener is registered */
+    onListening?: () => void;
+}
+
+const AutoConnectErrors = ({onListening}: AutoConnectErrorsProps) => {
+    const [failures, setFailures] = useState<(AutoConnectFailed & { host?: HostDefinition })[]>([]);
+
+    useEffect(() => {
+        const unlisten = onAutoConnectFailed(async (failed) => {
+            const host = (await getHosts()).find((host) => host.id === failed.hostId);
+            setFailures((failures) => [...failures, {...failed, host}]);
+        });
+        unlisten.then(() => onListening?.());
+        return () => {
+            unlisten.then((f) => f());
+        };
+    }, []);
+
+    const failure = failures[0];
+    const dismiss = () => setFailures((failures) => failures.slice(1));
+
+    return (
+        <Snackbar open={failure !== undefined} onClose={dismiss}>
+            <Alert severity={"error"} onClose={dismiss}>
+                {failure !== undefined &&
+                    `Auto-connect to ${failure.host?.host.address ?? `host ${failure.hostId}`} failed: ${failure.error}`
+                }
+            </Alert>
+        </Snackbar>
+    );
+};
+
+export default AutoConnectErrors;
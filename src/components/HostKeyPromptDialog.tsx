@@ -0,0 +1,62 @@
+import {Box, Button, Dialog, Paper, Stack, Typography} from "@mui/material";
+import {useEffect, useState} from "react";
+import {invoke} from "@tauri-apps/api";
+import {listen} from "@tauri-apps/api/event";
+
+interface HostKeyPrompt {
+    promptId: number;
+    host: string;
+    port: number;
+    keyType: string;
+    fingerprint: string;
+}
+
+interface HostKeyPromptDialogProps {
+    /** Called once the event listener is registered */
+    onListening?: () => void;
+}
+
+const HostKeyPromptDialog = ({onListening}: HostKeyPromptDialogProps) => {
+    const [prompts, setPrompts] = useState<HostKeyPrompt[]>([]);
+
+    useEffect(() => {
+        const unlisten = listen<HostKeyPrompt>("host-key-prompt", (event) => {
+            setPrompts((prompts) => [...prompts, event.payload]);
+        });
+        unlisten.then(() => onListening?.());
+        return () => {
+            unlisten.then((f) => f());
+        };
+    }, []);
+
+    const prompt = prompts[0];
+    const answer = async (accept: boolean) => {
+        setPrompts((prompts) => prompts.slice(1));
+        await invoke("answer_host_key", {promptId: prompt.promptId, accept});
+    };
+
+    return (
+        <Dialog open={prompt !== undefined} onClose={() => answer(false)}>
+            {prompt !== undefined &&
+                <Paper>
+                    <Box p={2}>
+                        <Typography mb={2} variant={"h5"}>Unknown host key</Typography>
+                        <Stack spacing={2}>
+                            <Typography>
+                                The authenticity of {prompt.host}:{prompt.port} can't be established.
+                            </Typography>
+                            <Typography>{prompt.keyType} key fingerprint is</Typography>
+                            <Typography fontFamily={"monospace"}>{prompt.fingerprint}</Typography>
+                            <Stack direction={"row"} spacing={2} justifyContent={"flex-end"}>
+                                <Button color={"error"} onClick={() => answer(false)}>Reject</Button>
+                                <Button onClick={() => answer(true)}>Trust</Button>
+                            </Stack>
+                        </Stack>
+                    </Box>
+                </Paper>
+            }
+        </Dialog>
+    );
+};
+
+export default HostKeyPromptDialog;
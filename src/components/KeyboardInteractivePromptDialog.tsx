@@ -0,0 +1,78 @@
+import {Box, Button, Dialog, FormGroup, FormLabel, Paper, Stack, TextField, Typography} from "@mui/material";
+import {useEffect, useState} from "react";
+import {invoke} from "@tauri-apps/api";
+import {listen} from "@tauri-apps/api/event";
+
+interface KeyboardInteractivePrompt {
+    promptId: number;
+    host: string;
+    user: string;
+    name: string;
+    instructions: string;
+    prompts: { prompt: string, echo: boolean }[];
+}
+
+interface KeyboardInteractivePromptDialogProps {
+    /** Called once the event listener is registered */
+    onListening?: () => void;
+}
+
+const KeyboardInteractivePromptDialog = ({onListening}: KeyboardInteractivePromptDialogProps) => {
+    const [prompts, setPrompts] = useState<KeyboardInteractivePrompt[]>([]);
+    const [answers, setAnswers] = useState<string[]>([]);
+
+    useEffect(() => {
+        const unlisten = listen<KeyboardInteractivePrompt>("keyboard-interactive-prompt", (event) => {
+            setPrompts((prompts) => [...prompts, event.payload]);
+        });
+        unlisten.then(() => onListening?.());
+        return () => {
+            unlisten.then((f) => f());
+        };
+    }, []);
+
+    const prompt = prompts[0];
+    const answer = async (answers: string[] | null) => {
+        setPrompts((prompts) => prompts.slice(1));
+        setAnswers([]);
+        await invoke("answer_keyboard_interactive", {promptId: prompt.promptId, answers});
+    };
+
+    return (
+        <Dialog open={prompt !== undefined} onClose={() => answer(null)}>
+            {prompt !== undefined &&
+                <Paper>
+                    <Box p={2}>
+                        <Typography mb={2} variant={"h5"}>
+                            {prompt.name || `${prompt.user}@${prompt.host}`}
+                        </Typography>
+                        <Stack spacing={2}>
+                            {prompt.instructions && <Typography>{prompt.instructions}</Typography>}
+                            {prompt.prompts.map((field, i) => (
+                                <FormGroup key={i}>
+                                    <FormLabel>{field.prompt}</FormLabel>
+                                    <TextField type={field.echo ? "text" : "password"}
+                                               autoFocus={i === 0}
+                                               value={answers[i] ?? ""}
+                                               onChange={(e) => setAnswers((answers) => {
+                                                   const next = [...answers];
+                                                   next[i] = e.target.value;
+                                                   return next;
+                                               })}/>
+                                </FormGroup>
+                            ))}
+                            <Stack direction={"row"} spacing={2} justifyContent={"flex-end"}>
+                                <Button color={"error"} onClick={() => answer(null)}>Cancel</Button>
+                                <Button onClick={() => answer(prompt.prompts.map((_, i) => answers[i] ?? ""))}>
+                                    Submit
+                                </Button>
+                            </Stack>
+                        </Stack>
+                    </Box>
+                </Paper>
+            }
+        </Dialog>
+    );
+};
+
+export default KeyboardInteractivePromptDialog;
@@ -14,8 +14,19 @@ import {
 import {Link as RouterLink, Outlet} from "react-router-dom";
 import CircleIcon from "@mui/icons-material/Circle";
 import {useSessionStore} from "../stores/sessions.ts";
+import {SessionState} from "../types.ts";
 import AddIcon from '@mui/icons-material/Add';
 import EditIcon from '@mui/icons-material/Edit';
+import LinkOffIcon from '@mui/icons-material/LinkOff';
+import DeleteIcon from '@mui/icons-material/Delete';
+
+const stateColors: Record<SessionState, "success" | "warning" | "info" | "disabled" | "error"> = {
+    connecting: "info",
+    connected: "success",
+    degraded: "warning",
+    disconnected: "disabled",
+    authFailed: "error",
+};
 
 interface SessionsTableProps {
 
@@ -23,6 +34,8 @@ interface SessionsTableProps {
 
 const SessionsTable = ({}: SessionsTableProps) => {
     const sessions = useSessionStore((state) => state.sessions);
+    const disconnect = useSessionStore((state) => state.disconnect);
+    const remove = useSessionStore((state) => state.remove);
 
     return (
         <>
@@ -45,7 +58,9 @@ const SessionsTable = ({}: SessionsTableProps) => {
                                     sx={{'&:last-child td, &:last-child th': {border: 0}}}
                                 >
                                     <TableCell align={"center"} sx={{maxWidth: "3rem"}}>
-                                        <CircleIcon color={"success"} fontSize={"small"}/>
+                                        <Tooltip title={session.state}>
+                                            <CircleIcon color={stateColors[session.state]} fontSize={"small"}/>
+                                        </Tooltip>
                                     </TableCell>
                                     <TableCell>{session.user}</TableCell>
                                     <TableCell>{session.host}</TableCell>
@@ -58,6 +73,19 @@ const SessionsTable = ({}: SessionsTableProps) => {
                                                 <EditIcon color={"primary"} fontSize={"small"}/>
                                             </IconButton>
                                         </Tooltip>
+                                        <Tooltip title={"Disconnect"}>
+                                            <span>
+                                                <IconButton color={"primary"} disabled={session.state === "disconnected"}
+                                                            onClick={() => disconnect(session)}>
+                                                    <LinkOffIcon fontSize={"small"}/>
+                                                </IconButton>
+                                            </span>
+                                        </Tooltip>
+                                        <Tooltip title={"Remove session"}>
+                                            <IconButton color={"error"} onClick={() => remove(session)}>
+                                                <DeleteIcon fontSize={"small"}/>
+                                            </IconButton>
+                                        </Tooltip>
                                     </TableCell>
                                 </TableRow>
                             ))}
@@ -0,0 +1,51 @@
+import {Box, Button, Dialog, Paper, Stack, TextField, Typography} from "@mui/material";
+import {useEffect, useState} from "react";
+import {unlockVault, vaultStatus} from "../types.ts";
+
+interface VaultUnlockDialogProps {
+
+}
+
+const VaultUnlockDialog = ({}: VaultUnlockDialogProps) => {
+    const [open, setOpen] = useState<boolean>(false);
+    const [masterPassword, setMasterPassword] = useState<string>("");
+    const [error, setError] = useState<string | undefined>(undefined);
+
+    useEffect(() => {
+        vaultStatus().then((status) => setOpen(status.exists && !status.unlocked));
+    }, []);
+
+    const unlock = async () => {
+        try {
+            await unlockVault(masterPassword);
+            setOpen(false);
+        } catch (e) {
+            setError((e as { message: string }).message);
+        }
+        setMasterPassword("");
+    };
+
+    return (
+        <Dialog open={open} onClose={() => setOpen(false)}>
+            <Paper>
+                <Box p={2}>
+                    <Typography mb={2} variant={"h5"}>Unlock vault</Typography>
+                    <Stack spacing={2}>
+                        <TextField type={"password"} label={"Master password"} value={masterPassword} autoFocus
+                                   onChange={(e) => setMasterPassword(e.target.value)}
+                                   onKeyDown={(e) => e.key === "Enter" && unlock()}/>
+                        {error !== undefined &&
+                            <Typography color={"error"}>{error}</Typography>
+                        }
+                        <Stack direction={"row"} spacing={2} justifyContent={"flex-end"}>
+                            <Button onClick={() => setOpen(false)}>Later</Button>
+                            <Button onClick={unlock}>Unlock</Button>
+                        </Stack>
+                    </Stack>
+                </Box>
+            </Paper>
+        </Dialog>
+    );
+};
+
+export default VaultUnlockDialog;
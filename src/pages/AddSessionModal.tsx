@@ -1,7 +1,22 @@
-import {Box, Button, Dialog, FormGroup, FormLabel, Paper, Stack, TextField, Typography} from "@mui/material";
+import {
+    Box,
+    Button,
+    Checkbox,
+    Dialog,
+    FormControlLabel,
+    FormGroup,
+    FormLabel,
+    MenuItem,
+    Paper,
+    Select,
+    Stack,
+    TextField,
+    Typography
+} from "@mui/material";
 import {Form, redirect, useActionData, useNavigate} from "react-router-dom";
-import {useState} from "react";
+import {useEffect, useState} from "react";
 import {useSessionStore} from "../stores/sessions.ts";
+import {AuthKind, ConfigHost, getConfigHosts, saveHost, Secret, setVaultEntry} from "../types.ts";
 
 interface AddSessionModalProps {
 
@@ -10,9 +25,32 @@ interface AddSessionModalProps {
 const AddSessionModal = ({}: AddSessionModalProps) => {
     const navigate = useNavigate();
     const error = useActionData() as Error | undefined;
+    const [configHosts, setConfigHosts] = useState<ConfigHost[]>([]);
     const [host, setHost] = useState<string>("");
+    const [port, setPort] = useState<number>(22);
     const [user, setUser] = useState<string>("");
+    const [method, setMethod] = useState<AuthKind["method"]>("password");
     const [password, setPassword] = useState<string>("");
+    const [keyPath, setKeyPath] = useState<string>("~/.ssh/id_ed25519");
+    const [save, setSave] = useState<boolean>(true);
+
+    useEffect(() => {
+        getConfigHosts().then(setConfigHosts).catch(() => setConfigHosts([]));
+    }, []);
+
+    const importConfigHost = (alias: string) => {
+        const configHost = configHosts.find((h) => h.alias === alias);
+        if (configHost === undefined) {
+            return;
+        }
+        setHost(configHost.host.address);
+        setPort(configHost.host.port);
+        setUser(configHost.user ?? "");
+        if (configHost.identityFiles.length > 0) {
+            setMethod("publicKey");
+            setKeyPath(configHost.identityFiles[0]);
+        }
+    };
 
     return (
         <Dialog open={true} onClose={() => navigate("..")}>
@@ -21,19 +59,79 @@ const AddSessionModal = ({}: AddSessionModalProps) => {
                     <Typography mb={2} variant={"h5"}>New session</Typography>
                     <Form method={"post"}>
                         <Stack spacing={2}>
+                            {configHosts.length > 0 &&
+                                <FormGroup>
+                                    <FormLabel>Import from ~/.ssh/config</FormLabel>
+                                    <Select value={""} displayEmpty
+                                            onChange={(e) => importConfigHost(e.target.value as string)}>
+                                        <MenuItem value={""} disabled>Choose a host</MenuItem>
+                                        {configHosts.map((h) => (
+                                            <MenuItem key={h.alias} value={h.alias}>{h.alias}</MenuItem>
+                                        ))}
+                                    </Select>
+                                </FormGroup>
+                            }
                             <FormGroup>
                                 <FormLabel>Host</FormLabel>
                                 <TextField value={host} name={"host"} onChange={(e) => setHost(e.target.value)}/>
                             </FormGroup>
+                            <FormGroup>
+                                <FormLabel>Port</FormLabel>
+                                <TextField type={"number"} value={port} name={"port"}
+                                           onChange={(e) => setPort(Number(e.target.value))}/>
+                            </FormGroup>
                             <FormGroup>
                                 <FormLabel>User</FormLabel>
                                 <TextField value={user} name={"user"} onChange={(e) => setUser(e.target.value)}/>
                             </FormGroup>
                             <FormGroup>
-                                <FormLabel>Password</FormLabel>
-                                <TextField type={"password"} name={"password"} value={password}
-                                           onChange={(e) => setPassword(e.target.value)}/>
+                                <FormLabel>Authentication</FormLabel>
+                                <Select value={method} name={"method"}
+                                        onChange={(e) => setMethod(e.target.value as AuthKind["method"])}>
+                                    <MenuItem value={"password"}>Password</MenuItem>
+                                    <MenuItem value={"publicKey"}>Key file</MenuItem>
+                                    <MenuItem value={"agent"}>SSH agent</MenuItem>
+                                    <MenuItem value={"keyboardInteractive"}>Keyboard-interactive (2FA)</MenuItem>
+                                </Select>
                             </FormGroup>
+                            {method === "publicKey" &&
+                                <FormGroup>
+                                    <FormLabel>Key file</FormLabel>
+                                    <TextField value={keyPath} name={"keyPath"}
+                                               onChange={(e) => setKeyPath(e.target.value)}/>
+                                </FormGroup>
+                            }
+                            {(method === "password" || method === "publicKey") &&
+                                <FormGroup>
+                                    <FormLabel>{method === "publicKey" ? "Passphrase" : "Password"}</FormLabel>
+                                    <TextField type={"password"} name={"password"} value={password}
+                                               onChange={(e) => setPassword(e.target.value)}/>
+                                </FormGroup>
+                            }
+                            {(method === "password" || method === "publicKey") &&
+                                <FormGroup>
+                                    <FormLabel>Vault entry</FormLabel>
+                                    <TextField name={"vaultEntry"}
+                                               placeholder={"optional, the secret above is stored under it"}/>
+                                </FormGroup>
+                            }
+                            <FormControlLabel label={"Save host"} control={
+                                <Checkbox name={"save"} checked={save} onChange={(e) => setSave(e.target.checked)}/>
+                            }/>
+                            {save &&
+                                <>
+                                    <FormGroup>
+                                        <FormLabel>Tags</FormLabel>
+                                        <TextField name={"tags"} placeholder={"comma separated"}/>
+                                    </FormGroup>
+                                    <FormGroup>
+                                        <FormLabel>Notes</FormLabel>
+                                        <TextField name={"notes"} multiline/>
+                                    </FormGroup>
+                                    <FormControlLabel label={"Connect on startup"}
+                                                      control={<Checkbox name={"autoConnect"}/>}/>
+                                </>
+                            }
                             <Button type={"submit"}>Connect</Button>
                             {error !== undefined &&
                                 <Typography color={"error"}>{error.message}</Typography>
@@ -49,11 +147,43 @@ const AddSessionModal = ({}: AddSessionModalProps) => {
 AddSessionModal.action = async ({request}: any) => {
     const formData = await request.formData() as FormData;
     const host = formData.get("host")! as string;
+    const port = Number(formData.get("port") ?? 22);
     const user = formData.get("user")! as string;
-    const password = formData.get("password")! as string;
+    const method = formData.get("method")! as AuthKind["method"];
+    const password = formData.get("password") as string | null;
+    const vaultEntry = formData.get("vaultEntry") as string | null || null;
+
+    const auth: AuthKind = method === "publicKey"
+        ? {method, keyPath: formData.get("keyPath")! as string}
+        : {method};
 
     try {
-        await useSessionStore.getState().connect(host, user, password);
+        let secret: Secret | undefined = password ? {plain: password} : undefined;
+        if (vaultEntry !== null) {
+            if (password) {
+                await setVaultEntry(vaultEntry, password);
+            }
+            secret = {vault: vaultEntry};
+        }
+
+        if (formData.get("save") !== null) {
+            const tags = (formData.get("tags") as string ?? "").split(",").map((t) => t.trim()).filter((t) => t);
+            const definition = {
+                id: 0,
+                host: {address: host, port},
+                user,
+                auth,
+                vaultEntry,
+                jumpHost: null,
+                tags,
+                notes: formData.get("notes") as string ?? "",
+                autoConnect: formData.get("autoConnect") !== null,
+            };
+            definition.id = await saveHost(definition);
+            await useSessionStore.getState().connectHost(definition, secret);
+        } else {
+            await useSessionStore.getState().connect(host, port, user, auth, secret);
+        }
     } catch (e) {
         return e;
     }
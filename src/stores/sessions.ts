@@ -1,29 +1,44 @@
 import {create} from 'zustand'
-import Session from "../types.ts";
+import {listen} from "@tauri-apps/api/event";
+import Session, {AuthKind, HostDefinition, Secret} from "../types.ts";
 
 interface SessionStore {
     sessions: Session[];
-    connect: (host: string, user: string, password: string) => Promise<void>;
-    reconnect: (session: Session, password: string) => Promise<void>;
-    remove: (session: Session) => void;
+    connect: (host: string, port: number, user: string, auth: AuthKind, secret?: Secret) => Promise<void>;
+    connectHost: (host: HostDefinition, secret?: Secret) => Promise<void>;
+    reconnect: (session: Session, auth: AuthKind, secret?: Secret) => Promise<void>;
+    disconnect: (session: Session) => Promise<void>;
+    remove: (session: Session) => Promise<void>;
 }
 
 export const useSessionStore = create<SessionStore>()(
     (set) => ({
         sessions: [],
-        connect: async (host, user, password) => {
-            const session = await Session.connect(host, user, password);
+        connect: async (host, port, user, auth, secret) => {
+            const session = await Session.connect(host, port, user, auth, secret);
             set((state) => ({sessions: [...state.sessions, session]}));
         },
-        reconnect: async (session, password) => {
-            await session.connect(password);
+        connectHost: async (host, secret) => {
+            const session = await Session.connectHost(host, secret);
+            set((state) => ({sessions: [...state.sessions, session]}));
+        },
+        reconnect: async (session, auth, secret) => {
+            await session.connect(auth, secret);
         },
-        remove: (session) => {
+        disconnect: async (session) => {
+            await session.disconnect();
+        },
+        remove: async (session) => {
+            await session.remove();
             set((state) => ({sessions: state.sessions.filter((s) => s !== session)}));
         }
     })
 );
 
-Session.sessions().then((sessions) => {
+const refresh = () => Session.sessions().then((sessions) => {
     useSessionStore.setState({sessions});
 });
+
+refresh();
+listen("sessions-changed", refresh);
+listen("session-state-changed", refresh);
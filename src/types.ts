@@ -1,42 +1,337 @@
 import {invoke} from "@tauri-apps/api";
+import {listen} from "@tauri-apps/api/event";
+
+export type AuthKind =
+    | { method: "password" }
+    | { method: "publicKey", keyPath: string }
+    | { method: "agent" }
+    | { method: "keyboardInteractive" };
+
+/** A password or key passphrase, given as is or by vault entry name */
+export type Secret = { plain: string } | { vault: string };
+
+export type HostDefinition = {
+    id: number,
+    host: { address: string, port: number },
+    user: string,
+    auth: AuthKind,
+    vaultEntry: string | null,
+    jumpHost: number | null,
+    tags: string[],
+    notes: string,
+    autoConnect: boolean,
+};
+
+export type ConfigHost = {
+    alias: string,
+    host: { address: string, port: number },
+    user: string | null,
+    identityFiles: string[],
+    proxyJump: string[],
+};
+
+export type SessionState = "connecting" | "connected" | "degraded" | "disconnected" | "authFailed";
+
+export type Hop = { sessionId: number, user: string, addrs: [string, number] };
+
+export type HostFacts = {
+    os: { id: string, idLike: string[], name: string | null, versionId: string | null, prettyName: string | null } | null,
+    kernel: string,
+    kernelRelease: string,
+    arch: string,
+    init: "systemd" | "openRc" | "runit" | "s6" | "sysVinit" | "unknown",
+    shell: string | null,
+    userland: "gnu" | "busybox" | "bsd" | "unknown",
+    hasSudo: boolean,
+    hasDoas: boolean,
+    virtualization:
+        | { kind: "bareMetal" | "unknown" }
+        | { kind: "container" | "vm", technology: string },
+};
 
 class Session {
     private _id: number = -1;
     public readonly host: string;
+    public readonly port: number;
     public readonly user: string;
+    public readonly hops: Hop[];
+    public state: SessionState = "connected";
+    public facts: HostFacts | null = null;
 
-    private constructor(host: string, user: string) {
+    private constructor(host: string, user: string, hops: Hop[] = [], port: number = 22) {
         this.host = host;
+        this.port = port;
         this.user = user;
+        this.hops = hops;
     }
 
-    public static async connect(host: string, user: string, password: string): Promise<Session> {
-        const session = new Session(host, user);
-        await session.connect(password);
+    public static async connect(host: string, port: number, user: string, auth: AuthKind, secret?: Secret, jumpSession?: number): Promise<Session> {
+        const session = new Session(host, user, [], port);
+        await session.connect(auth, secret, jumpSession);
+        return session;
+    }
+
+    public static async connectHost(host: HostDefinition, secret?: Secret): Promise<Session> {
+        const session = new Session(host.host.address, host.user, [], host.host.port);
+        session._id = await invoke<number>("connect_host", {hostId: host.id, secret});
         return session;
     }
 
     public static async sessions(): Promise<Session[]> {
-        type SessionInfo = {addrs: [string, number], user: string, };
+        type SessionInfo = {
+            id: number,
+            addrs: [string, number],
+            user: string,
+            hops: Hop[],
+            state: SessionState,
+            facts: HostFacts | null,
+        };
         const sessions_info = await invoke<SessionInfo[]>("get_sessions");
         return sessions_info.map((info) => {
-            const [host, _] = info.addrs;
-            return new Session(host, info.user);
+            const [host, port] = info.addrs;
+            const session = new Session(host, info.user, info.hops, port);
+            session._id = info.id;
+            session.state = info.state;
+            session.facts = info.facts;
+            return session;
         });
     }
 
     get id(): number { return this._id;}
 
-    public async connect(password: string) {
+    public async connect(auth: AuthKind, secret?: Secret, jumpSession?: number) {
         await invoke<number>("start_session", {
                 req: {
-                    addr: [this.host, 22],
+                    host: {address: this.host, port: this.port},
                     user: this.user,
-                    password
+                    auth,
+                    secret,
+                    jumpSession
                 }
             }
         ).then(id => this._id = id);
     }
+
+    public async disconnect() {
+        await invoke<void>("disconnect_session", {sessionId: this._id});
+    }
+
+    public async remove() {
+        await invoke<void>("remove_session", {sessionId: this._id});
+    }
 }
 
+export const getConfigHosts = () => invoke<ConfigHost[]>("get_config_hosts");
+
+export const getHosts = () => invoke<HostDefinition[]>("get_hosts");
+
+export const saveHost = (host: HostDefinition) => invoke<number>("save_host", {host});
+
+export const removeHost = (hostId: number) => invoke<void>("remove_host", {hostId});
+
+/** Ids for cancellable commands are picked by the caller */
+export const newCommandId = () => Math.floor(Math.random() * Number.MAX_SAFE_INTEGER);
+
+export type Address = {
+    family: "ipv4" | "ipv6",
+    /** Address and prefix length */
+    cidr: [string, number],
+    broadcast: string | null,
+    scope: "global" | "site" | "link" | "host",
+    /** Seconds left, null meaning forever */
+    lifetime: { valid: number | null, preferred: number | null } | null,
+    flags: ("dynamic" | "secondary" | "temporary" | "deprecated" | "tentative" | "noPrefixRoute" | "manageTempAddr")[],
+};
+
+export type Interface = {
+    name: string,
+    /** The six bytes, null for layer 3 links like wireguard */
+    mac: number[] | null,
+    addresses: Address[],
+    status: "up" | "down" | "unknown" | "dormant" | "lowerLayerDown" | "notPresent" | "testing",
+    mtu: number | null,
+    flags: string[],
+    linkType: "ether" | "loopback" | "bridge" | "vlan" | "bond" | "wireguard" | "veth" | "tun" | "other",
+    /** Mbit/s */
+    speed: number | null,
+    duplex: "full" | "half" | null,
+    master: string | null,
+    parent: string | null,
+};
+
+export const getInterfaces = (sessionId: number, commandId?: number, timeoutMs?: number) =>
+    invoke<Interface[]>("get_interfaces", {sessionId, commandId, timeoutMs});
+
+export type Counters = { bytes: number, packets: number, errors: number, drops: number };
+
+export type InterfaceCounters = { name: string, rx: Counters, tx: Counters };
+
+/** `rx` and `tx` are per second, the totals as of the latest reading */
+export type Throughput = { name: string, rx: Counters, tx: Counters, rxTotals: Counters, txTotals: Counters };
+
+export const getTrafficCounters = (sessionId: number) =>
+    invoke<InterfaceCounters[]>("get_traffic_counters", {sessionId});
+
+/**
+ * Calls `onSample` with the rates of every interface each `intervalMs`, one second by default.
+ * `done` settles once `stop` is called, or with an error if sampling fails.
+ */
+export const watchThroughput = async (sessionId: number, onSample: (interfaces: Throughput[]) => void,
+                                      intervalMs?: number) => {
+    const watchId = newCommandId();
+    const unlisten = await listen<{ watchId: number, interfaces: Throughput[] }>("throughput", (event) => {
+        if (event.payload.watchId === watchId) {
+            onSample(event.payload.interfaces);
+        }
+    });
+    const done = invoke<void>("watch_throughput", {sessionId, watchId, intervalMs}).finally(unlisten);
+    return {
+        done,
+        stop: async () => {
+            await cancelCommand(watchId);
+            await done;
+        },
+    };
+};
+
+export type OutputChunk = { stream: "stdout" | "stderr", data: string };
+
+export type CommandOutput = {
+    stdout: string,
+    stderr: string,
+    exitStatus: number | null,
+    exitSignal: string | null,
+    duration: { secs: number, nanos: number },
+};
+
+/**
+ * Runs a command on a session, `onChunk` gets its output as it arrives. Cancel it with `cancelCommand(jobId)`.
+ * `elevated` runs it as root, failing with an `elevationDenied` error if sudo or doas refuse.
+ */
+export const runCommand = async (sessionId: number, jobId: number, command: string,
+                                 onChunk: (chunk: OutputChunk) => void, timeoutMs?: number, elevated?: boolean) => {
+    const unlisten = await listen<OutputChunk & { jobId: number }>("job-output", (event) => {
+        if (event.payload.jobId === jobId) {
+            onChunk(event.payload);
+        }
+    });
+    try {
+        return await invoke<CommandOutput>("run_command", {sessionId, jobId, command, timeoutMs, elevated});
+    } finally {
+        unlisten();
+    }
+};
+
+export type TerminalEvent =
+    | { terminalId: number, kind: "output", data: string }
+    | { terminalId: number, kind: "exited", exitStatus: number | null };
+
+/** Opens a shell on a PTY, everything it prints goes to `onEvent` */
+export const openTerminal = async (sessionId: number, cols: number, rows: number,
+                                   onEvent: (event: TerminalEvent) => void) => {
+    let terminalId: number | undefined;
+    const early: TerminalEvent[] = [];
+    const unlisten = await listen<TerminalEvent>("terminal-event", (event) => {
+        if (terminalId === undefined) {
+            early.push(event.payload);
+        } else if (event.payload.terminalId === terminalId) {
+            onEvent(event.payload);
+        }
+    });
+    try {
+        terminalId = await invoke<number>("open_terminal", {sessionId, cols, rows});
+    } catch (e) {
+        unlisten();
+        throw e;
+    }
+    early.filter((event) => event.terminalId === terminalId).forEach(onEvent);
+    return {
+        terminalId,
+        write: (data: string) => invoke<void>("write_terminal", {sessionId, terminalId, data}),
+        resize: (cols: number, rows: number) => invoke<void>("resize_terminal", {sessionId, terminalId, cols, rows}),
+        close: async () => {
+            unlisten();
+            await invoke<void>("close_terminal", {sessionId, terminalId});
+        },
+    };
+};
+
+export type FileInfo = {
+    name: string,
+    path: string,
+    kind: "dir" | "file" | "symlink" | "other",
+    size: number,
+    permissions: number | null,
+    modified: number | null,
+    user: string | null,
+    group: string | null,
+};
+
+export type TransferProgress = { transferId: number, transferred: number, total: number | null };
+
+export const sftp = (sessionId: number) => ({
+    listDir: (path: string) => invoke<FileInfo[]>("sftp_list_dir", {sessionId, path}),
+    stat: (path: string) => invoke<FileInfo>("sftp_stat", {sessionId, path}),
+    readFile: (path: string) => invoke<string>("sftp_read_file", {sessionId, path}),
+    writeFile: (path: string, contents: string) => invoke<void>("sftp_write_file", {sessionId, path, contents}),
+    mkdir: (path: string) => invoke<void>("sftp_mkdir", {sessionId, path}),
+    rename: (from: string, to: string) => invoke<void>("sftp_rename", {sessionId, from, to}),
+    remove: (path: string) => invoke<void>("sftp_remove", {sessionId, path}),
+    /** Cancel with `cancelCommand(transferId)` */
+    download: (transferId: number, remotePath: string, localPath: string) =>
+        invoke<void>("sftp_download", {sessionId, transferId, remotePath, localPath}),
+    upload: (transferId: number, localPath: string, remotePath: string) =>
+        invoke<void>("sftp_upload", {sessionId, transferId, localPath, remotePath}),
+});
+
+export const onTransferProgress = (handler: (progress: TransferProgress) => void) =>
+    listen<TransferProgress>("transfer-progress", (event) => handler(event.payload));
+
+/** `bindPort: 0` lets the OS (or the server, for remote forwards) pick a free port.
+ * Dynamic forwards are SOCKS proxies without authentication, they can only bind to loopback. */
+export type ForwardSpec =
+    | { kind: "local", bindAddress: string, bindPort: number, remoteHost: string, remotePort: number }
+    | { kind: "remote", bindAddress: string, bindPort: number, localHost: string, localPort: number }
+    | { kind: "dynamic", bindAddress: string, bindPort: number };
+
+export type ForwardInfo = ForwardSpec & {
+    id: number,
+    bytesSent: number,
+    bytesReceived: number,
+    activeConnections: number,
+    error: string | null,
+};
+
+export const getForwards = (sessionId: number) => invoke<ForwardInfo[]>("get_forwards", {sessionId});
+
+export const startForward = (sessionId: number, forward: ForwardSpec) =>
+    invoke<ForwardInfo>("start_forward", {sessionId, forward});
+
+export const stopForward = (sessionId: number, forwardId: number) =>
+    invoke<void>("stop_forward", {sessionId, forwardId});
+
+export const cancelCommand =(commandId: number) => invoke<void>("cancel_command", {commandId});
+
+/** Saved hosts flagged for it are connected once this is called, prompts must be listened for by then */
+export const frontendReady = () => invoke<void>("frontend_ready");
+
+export type AutoConnectFailed = { hostId: number, error: string };
+
+export const onAutoConnectFailed = (handler: (failed: AutoConnectFailed) => void) =>
+    listen<AutoConnectFailed>("auto-connect-failed", (event) => handler(event.payload));
+
+export type VaultStatus = { exists: boolean, unlocked: boolean };
+
+export const vaultStatus = () => invoke<VaultStatus>("vault_status");
+
+export const unlockVault = (masterPassword: string) => invoke<void>("unlock_vault", {masterPassword});
+
+export const lockVault = () => invoke<void>("lock_vault");
+
+export const getVaultEntries = () => invoke<string[]>("get_vault_entries");
+
+export const setVaultEntry = (entry: string, secret: string) => invoke<void>("set_vault_entry", {entry, secret});
+
+export const removeVaultEntry = (entry: string) => invoke<void>("remove_vault_entry", {entry});
+
 export default Session;
\ No newline at end of file